@@ -1,6 +1,4 @@
-use bevy::prelude::*;
-
-use ship_frame::{client, server};
+use ship_frame::{client, server, Position};
 
 fn main() {
     // let mut app = App::new();
@@ -17,20 +15,20 @@ fn main() {
 
     let mut server_frame = server::ShipFrame::<()>::new_from_beam(
         &mut id_world,
-        Vec3::new(0., 0., 0.),
-        Vec3::new(5., 0., 0.),
+        Position::new(0., 0., 0.),
+        Position::new(5., 0., 0.),
         (),
     );
 
     let (vertex_b, _) = server_frame.iter_vertices().nth(1).unwrap();
 
     let new_beam_message = server_frame.serialize();
-    let mut update_messages = Vec::new();
+    let update_messages =
+        vec![server_frame.add_beam_extend(&mut id_world, vertex_b, Position::new(5., 5., 0.), ())];
 
-    update_messages.push(server_frame.add_beam_extend(
-        &mut id_world,
-        vertex_b,
-        Vec3::new(5., 5., 0.),
-        (),
-    ))
+    let mut client_frame = client::ShipFrame::new(new_beam_message);
+
+    for update in update_messages {
+        client_frame.apply_update(update).unwrap();
+    }
 }
@@ -1,34 +1,687 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    marker::PhantomData,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+
 use crate::{
-    graph::Graph,
-    messages::{FrameUpdate, SerializedGraph},
+    graph::{FrameView, Graph},
+    messages::{
+        ApplyError, BatchApplyError, FrameUpdate, FrameUpdateBatch, Sequenced, SerializedGraph,
+        UnapplyContext, UpdateEffect, ValidationError,
+    },
+    VertexId,
 };
 
-pub struct ShipFrame<B> {
-    graph: Graph<B>,
+/// The most updates [ShipFrame::apply_ordered] will hold on to while waiting for a missing one.
+pub const MAX_BUFFERED_UPDATES: usize = 256;
+
+/// A client's copy of a frame, kept in sync by applying the updates
+/// recorded by the server's [crate::server::ShipFrame].
+#[derive(Component)]
+pub struct ShipFrame<B, V = ()> {
+    graph: Graph<B, V>,
+    next_seq: u64,
+    buffered: BTreeMap<u64, FrameUpdate<B, V>>,
+    needs_resync: bool,
 }
 
-impl<B> ShipFrame<B> {
-    pub fn new(serialized: SerializedGraph<B>) -> Self {
+impl<B, V> ShipFrame<B, V> {
+    /// Panics if the graph is invalid, use [ShipFrame::try_new] for graphs received from the server.
+    pub fn new(serialized: SerializedGraph<B, V>) -> Self {
+        ShipFrame::from_graph(serialized.into(), 0)
+    }
+
+    /// Same as [ShipFrame::new] but returns an error instead of panicking if the graph is invalid.
+    pub fn try_new(serialized: SerializedGraph<B, V>) -> Result<Self, ValidationError> {
+        Ok(ShipFrame::from_graph(serialized.try_into_graph()?, 0))
+    }
+
+    /// Creates a frame from a server snapshot,
+    /// that will apply ordered updates starting from the snapshot's sequence number.
+    ///
+    /// Panics if the graph is invalid, use [ShipFrame::try_from_snapshot] for snapshots received from the server.
+    pub fn from_snapshot(serialized: SerializedGraph<B, V>, next_seq: u64) -> Self {
+        ShipFrame::from_graph(serialized.into(), next_seq)
+    }
+
+    /// Same as [ShipFrame::from_snapshot] but returns an error instead of panicking if the graph is invalid.
+    pub fn try_from_snapshot(
+        serialized: SerializedGraph<B, V>,
+        next_seq: u64,
+    ) -> Result<Self, ValidationError> {
+        Ok(ShipFrame::from_graph(
+            serialized.try_into_graph()?,
+            next_seq,
+        ))
+    }
+
+    fn from_graph(graph: Graph<B, V>, next_seq: u64) -> Self {
         ShipFrame {
-            graph: serialized.into(),
+            graph,
+            next_seq,
+            buffered: BTreeMap::new(),
+            needs_resync: false,
         }
     }
 
-    pub fn apply_update(&mut self, update: FrameUpdate<B>) {
-        match update {
+    pub fn graph(&self) -> &Graph<B, V> {
+        &self.graph
+    }
+
+    /// Consumes the frame, returning it's graph. Any buffered updates are discarded.
+    pub fn into_graph(self) -> Graph<B, V> {
+        self.graph
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.graph.vertex_count()
+    }
+
+    pub fn beam_count(&self) -> usize {
+        self.graph.beam_count()
+    }
+
+    /// Removes all vertices and beams and discards any buffered updates,
+    /// keeping the allocated capacity so the frame can be reused.
+    pub fn clear(&mut self) {
+        self.graph.clear();
+        self.buffered.clear();
+    }
+
+    /// Replaces the graph with a fresh snapshot from the server,
+    /// discarding any buffered updates and clearing [ShipFrame::needs_resync].
+    ///
+    /// `next_seq` is the sequence number the snapshot was taken at, see [crate::server::ShipFrame::snapshot],
+    /// [ShipFrame::apply_ordered] continues from it and ignores the updates the snapshot already contains.
+    ///
+    /// The frame is left unmodified if the snapshot is invalid.
+    pub fn reset_from(
+        &mut self,
+        serialized: SerializedGraph<B, V>,
+        next_seq: u64,
+    ) -> Result<(), ValidationError> {
+        self.graph = serialized.try_into_graph()?;
+        self.next_seq = next_seq;
+        self.buffered.clear();
+        self.needs_resync = false;
+        Ok(())
+    }
+
+    /// If an update failed to apply, meaning the frame is out of sync with the server
+    /// and should be replaced using [ShipFrame::reset_from].
+    pub fn needs_resync(&self) -> bool {
+        self.needs_resync
+    }
+
+    /// Applies an update from the server.
+    ///
+    /// The frame is left unmodified if an error is returned,
+    /// which means it is out of sync with the server and [ShipFrame::needs_resync] is set.
+    pub fn apply_update(&mut self, update: FrameUpdate<B, V>) -> Result<(), ApplyError> {
+        self.apply_update_reporting(update).map(|_| ())
+    }
+
+    /// Same as [ShipFrame::apply_update] but also returns what the update changed,
+    /// eg. to spawn meshes for the vertices it created.
+    pub fn apply_update_reporting(
+        &mut self,
+        update: FrameUpdate<B, V>,
+    ) -> Result<UpdateEffect, ApplyError> {
+        let result = self.graph.apply_update_reporting(update);
+        self.needs_resync |= result.is_err();
+        result
+    }
+
+    /// Same as [ShipFrame::apply_update] but also returns the context needed
+    /// to unapply the update with [ShipFrame::unapply_update].
+    pub fn apply_update_with_context(
+        &mut self,
+        update: FrameUpdate<B, V>,
+    ) -> Result<UnapplyContext<B, V>, ApplyError>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let context = update.unapply_context(&self.graph);
+        self.apply_update(update)?;
+        Ok(context.expect("The update applied to the graph."))
+    }
+
+    /// Reverts an update that was the last one applied to the frame,
+    /// eg. to rewind a recorded stream of updates by unapplying them in reverse order.
+    ///
+    /// `context` has to supply what the update overwrote, see [FrameUpdate::unapply_context],
+    /// which is only optional for adding a beam or removing one that carries it's recovery data.
+    ///
+    /// Returns [ApplyError::MissingContext] if it doesn't, in which case the frame is left unmodified.
+    /// Any other error means the update wasn't the last one applied and [ShipFrame::needs_resync] is set.
+    pub fn unapply_update(
+        &mut self,
+        update: &FrameUpdate<B, V>,
+        context: &UnapplyContext<B, V>,
+    ) -> Result<(), ApplyError>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let inverse = update.inverse_from_context(context)?;
+        self.apply_update(inverse)
+    }
+
+    /// Applies updates in the order of their sequence numbers,
+    /// holding on to updates that arrive early until the missing ones arrive.
+    ///
+    /// Updates with a sequence number that was already applied are ignored.
+    /// If more than [MAX_BUFFERED_UPDATES] are waiting, they are all discarded,
+    /// [ShipFrame::needs_resync] is set and [ApplyError::TooManyBuffered] is returned.
+    /// The missing update is still expected next, so the frame can't move forward
+    /// until it's reset from a snapshot with [ShipFrame::reset_from].
+    /// A failed update is still counted as applied.
+    pub fn apply_ordered(
+        &mut self,
+        update: Sequenced<FrameUpdate<B, V>>,
+    ) -> Result<(), ApplyError> {
+        if update.seq < self.next_seq {
+            return Ok(());
+        }
+
+        self.buffered.insert(update.seq, update.inner);
+
+        if self.buffered.len() > MAX_BUFFERED_UPDATES {
+            self.buffered.clear();
+            self.needs_resync = true;
+            return Err(ApplyError::TooManyBuffered);
+        }
+
+        while let Some(update) = self.buffered.remove(&self.next_seq) {
+            self.next_seq += 1;
+            self.apply_update(update)?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies a batch of updates in order, stopping at the first one that fails.
+    ///
+    /// Updates before the failed one stay applied.
+    pub fn apply_batch(&mut self, batch: FrameUpdateBatch<B, V>) -> Result<(), BatchApplyError> {
+        for (index, update) in batch.0.into_iter().enumerate() {
+            self.apply_update(update)
+                .map_err(|error| BatchApplyError { index, error })?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as [ShipFrame::apply_batch] but updates that reference a vertex that doesn't exist yet
+    /// wait until an update in the batch creates it, eg. for prefab assembly messages sent in any order.
+    ///
+    /// Otherwise updates are applied in order. If a vertex isn't in the frame or created by any
+    /// update, the earliest update waiting on it fails with [ApplyError::MissingVertex].
+    ///
+    /// Updates applied before the failed one stay applied and [ShipFrame::needs_resync] is set.
+    pub fn apply_batch_resolving(
+        &mut self,
+        updates: Vec<FrameUpdate<B, V>>,
+    ) -> Result<(), BatchApplyError> {
+        let mut queue: VecDeque<(usize, FrameUpdate<B, V>)> =
+            updates.into_iter().enumerate().collect();
+        let mut waiting: HashMap<VertexId, Vec<_>> = HashMap::new();
+
+        while let Some((index, update)) = queue.pop_front() {
+            if let Some(missing) = self.missing_vertex(&update) {
+                waiting.entry(missing).or_default().push((index, update));
+                continue;
+            }
+
+            let effect = self
+                .apply_update_reporting(update)
+                .map_err(|error| BatchApplyError { index, error })?;
+
+            // updates waiting on a new vertex go next, in their batch order
+            for vertex in effect.created_vertices.iter().rev() {
+                if let Some(updates) = waiting.remove(vertex) {
+                    for waiting_update in updates.into_iter().rev() {
+                        queue.push_front(waiting_update);
+                    }
+                }
+            }
+        }
+
+        let unresolved = waiting
+            .into_iter()
+            .flat_map(|(vertex, updates)| {
+                updates.into_iter().map(move |(index, _)| (index, vertex))
+            })
+            .min_by_key(|&(index, _)| index);
+
+        if let Some((index, vertex)) = unresolved {
+            self.needs_resync = true;
+
+            return Err(BatchApplyError {
+                index,
+                error: ApplyError::MissingVertex(vertex),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The first vertex an update references that isn't in the frame, not including ones it creates.
+    fn missing_vertex(&self, update: &FrameUpdate<B, V>) -> Option<VertexId> {
+        let referenced = match *update {
             FrameUpdate::AddBeam {
                 vertex_a,
-                position_a,
+                ref new_a,
                 vertex_b,
-                position_b,
-                beam_data,
-            } => {
-                self.graph
-                    .add_beam(vertex_a, position_a, vertex_b, position_b, beam_data);
+                ref new_b,
+                ..
+            } => [
+                new_a.is_none().then_some(vertex_a),
+                new_b.is_none().then_some(vertex_b),
+            ],
+            FrameUpdate::RemoveBeam { id, .. } | FrameUpdate::UpdateBeamData { id, .. } => {
+                [Some(id.down_vertex()), Some(id.up_vertex())]
+            }
+            FrameUpdate::MoveVertex { id, .. } | FrameUpdate::UpdateVertexData { id, .. } => {
+                [Some(id), None]
+            }
+        };
+
+        referenced
+            .into_iter()
+            .flatten()
+            .find(|&id| self.graph.get_vertex(id).is_none())
+    }
+}
+
+impl<B, V> FrameView<B, V> for ShipFrame<B, V> {
+    fn graph(&self) -> &Graph<B, V> {
+        &self.graph
+    }
+}
+
+/// A [ShipFrame] with local edits applied on top before the server confirms them.
+///
+/// Predictions are numbered in order, the number should be sent to the server
+/// along with the edit so that the server's update can be matched to it.
+pub struct PredictedFrame<B, V = ()> {
+    confirmed: ShipFrame<B, V>,
+    predicted: Graph<B, V>,
+    pending: VecDeque<(u64, FrameUpdate<B, V>)>,
+    next_prediction: u64,
+}
+
+impl<B: Clone + PartialEq, V: Clone + PartialEq> PredictedFrame<B, V> {
+    pub fn new(confirmed: ShipFrame<B, V>) -> Self {
+        PredictedFrame {
+            predicted: SerializedGraph::from(&confirmed.graph).into(),
+            confirmed,
+            pending: VecDeque::new(),
+            next_prediction: 0,
+        }
+    }
+
+    /// The frame as last confirmed by the server.
+    pub fn confirmed(&self) -> &ShipFrame<B, V> {
+        &self.confirmed
+    }
+
+    /// The confirmed frame with all pending predictions applied.
+    pub fn predicted(&self) -> &Graph<B, V> {
+        &self.predicted
+    }
+
+    /// The number of predictions that haven't been confirmed or rejected yet.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Applies a local edit before the server confirms it, returning the number of the prediction.
+    ///
+    /// Nothing is changed if the edit can't be applied to the predicted frame.
+    pub fn predict(&mut self, update: FrameUpdate<B, V>) -> Result<u64, ApplyError> {
+        self.predicted.apply_update(update.clone())?;
+
+        let prediction = self.next_prediction;
+        self.next_prediction += 1;
+        self.pending.push_back((prediction, update));
+
+        Ok(prediction)
+    }
+
+    /// Applies an update from the server,
+    /// along with the number of the prediction it confirms if it's the result of one.
+    ///
+    /// Confirming a prediction discards any older pending ones, as the server must have rejected them.
+    /// If the server's update isn't the [same edit](FrameUpdate::same_edit) as what was predicted, the predicted frame is
+    /// rebuilt from the confirmed frame by replaying the remaining predictions,
+    /// dropping any that no longer apply.
+    ///
+    /// If an error is returned the confirmed frame is out of sync with the server,
+    /// see [ShipFrame::apply_update].
+    pub fn apply_confirmed(
+        &mut self,
+        update: FrameUpdate<B, V>,
+        prediction: Option<u64>,
+    ) -> Result<(), ApplyError> {
+        self.confirmed.apply_update(update.clone())?;
+
+        let Some(prediction) = prediction else {
+            // an update from someone else, which still applies unless it conflicts with a prediction
+            if self.predicted.apply_update(update).is_err() {
+                self.rebuild();
+            }
+
+            return Ok(());
+        };
+
+        let mut matched = false;
+        let mut rejected = false;
+
+        while let Some(&(number, _)) = self.pending.front() {
+            if number > prediction {
+                break;
             }
-            FrameUpdate::RemoveBeam { id } => {
-                self.graph.remove_beam(id);
+
+            let (number, predicted_update) = self.pending.pop_front().unwrap();
+
+            if number == prediction {
+                matched = predicted_update.same_edit(&update);
+            } else {
+                rejected = true;
             }
         }
+
+        if !matched || rejected {
+            self.rebuild();
+        }
+
+        Ok(())
+    }
+
+    /// Discards a prediction the server rejected, rebuilding the predicted frame without it.
+    pub fn reject(&mut self, prediction: u64) {
+        self.pending.retain(|&(number, _)| number != prediction);
+        self.rebuild();
+    }
+
+    /// Replaces the confirmed frame with a fresh snapshot from the server,
+    /// then rebuilds the predicted frame by replaying the pending predictions.
+    ///
+    /// See [ShipFrame::reset_from].
+    pub fn reset_from(
+        &mut self,
+        serialized: SerializedGraph<B, V>,
+        next_seq: u64,
+    ) -> Result<(), ValidationError> {
+        self.confirmed.reset_from(serialized, next_seq)?;
+        self.rebuild();
+        Ok(())
+    }
+
+    fn rebuild(&mut self) {
+        self.predicted = SerializedGraph::from(&self.confirmed.graph).into();
+
+        self.pending
+            .retain(|(_, update)| self.predicted.apply_update(update.clone()).is_ok());
+    }
+}
+
+/// An update from the server for the [ShipFrame] on an entity.
+#[derive(Event)]
+pub struct ClientFrameUpdate<B, V = ()> {
+    pub frame: Entity,
+    pub update: FrameUpdate<B, V>,
+}
+
+/// Applies [ClientFrameUpdate] events to [ShipFrame]s every frame in [PreUpdate].
+pub struct ClientFramePlugin<B, V = ()>(PhantomData<fn() -> (B, V)>);
+
+impl<B, V> Default for ClientFramePlugin<B, V> {
+    fn default() -> Self {
+        ClientFramePlugin(PhantomData)
+    }
+}
+
+impl<B: Send + Sync + 'static, V: Send + Sync + 'static> Plugin for ClientFramePlugin<B, V> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ClientFrameUpdate<B, V>>();
+        app.add_systems(PreUpdate, apply_client_frame_updates::<B, V>);
+    }
+}
+
+fn apply_client_frame_updates<B: Send + Sync + 'static, V: Send + Sync + 'static>(
+    mut updates: ResMut<Events<ClientFrameUpdate<B, V>>>,
+    mut frames: Query<&mut ShipFrame<B, V>>,
+) {
+    for ClientFrameUpdate { frame, update } in updates.drain() {
+        let Ok(mut ship_frame) = frames.get_mut(frame) else {
+            warn!("Received a frame update for {} which has no frame.", frame);
+            continue;
+        };
+
+        if let Err(error) = ship_frame.apply_update(update) {
+            warn!("Failed to apply a frame update to {}: {:?}", frame, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messages::RemovedBeamData,
+        server,
+        server::{FrameIdWorld, IdSource},
+        BeamId, Position, Scalar,
+    };
+
+    #[test]
+    fn corrupt_snapshot_is_rejected() {
+        let mut id_world = FrameIdWorld::default();
+        let server =
+            server::ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32);
+
+        let mut snapshot = server.serialize();
+        snapshot.vertices.pop();
+
+        assert!(ShipFrame::try_new(snapshot.clone()).is_err());
+        assert!(ShipFrame::try_from_snapshot(snapshot.clone(), 0).is_err());
+        assert!(server::ShipFrame::try_from_serialized(snapshot.clone()).is_err());
+        assert!(id_world.try_map_frame(snapshot).is_err());
+    }
+
+    #[test]
+    fn too_many_buffered_needs_resync() {
+        let mut id_world = FrameIdWorld::default();
+        let server =
+            server::ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32);
+        let mut client = ShipFrame::new(server.serialize());
+        let vertex = server.iter_vertices().next().unwrap().0;
+
+        let mut result = Ok(());
+
+        for seq in 1..=MAX_BUFFERED_UPDATES as u64 + 1 {
+            result = client.apply_ordered(Sequenced {
+                seq,
+                inner: FrameUpdate::MoveVertex {
+                    id: vertex,
+                    position: Position::Y,
+                },
+            });
+        }
+
+        assert_eq!(result, Err(ApplyError::TooManyBuffered));
+        assert!(client.needs_resync());
+    }
+
+    #[test]
+    fn ordered_client_continues_after_reset() {
+        let mut id_world = FrameIdWorld::default();
+        let mut server =
+            server::ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32);
+        let mut client = ShipFrame::new(server.serialize());
+
+        let mut vertex = server.iter_vertices().nth(1).unwrap().0;
+
+        for (seq, step) in (1..12).enumerate() {
+            let update =
+                server.add_beam_extend(&mut id_world, vertex, Position::Y * step as Scalar, step);
+
+            if let FrameUpdate::AddBeam { vertex_b, .. } = update {
+                vertex = vertex_b;
+            }
+
+            // the first update is lost
+            if seq > 0 {
+                let _ = client.apply_ordered(Sequenced {
+                    seq: seq as u64,
+                    inner: update,
+                });
+            }
+        }
+
+        assert_eq!(client.beam_count(), 1);
+
+        let (snapshot, next_seq) = server.snapshot();
+        client.reset_from(snapshot, next_seq).unwrap();
+
+        let update = server.add_beam_extend(&mut id_world, vertex, Position::Z, 12);
+        client
+            .apply_ordered(Sequenced {
+                seq: next_seq,
+                inner: update,
+            })
+            .unwrap();
+
+        assert_eq!(client.beam_count(), 13);
+        assert!(client.graph() == server.graph());
+    }
+
+    #[test]
+    fn unapply_restores_every_update() {
+        let mut id_world = FrameIdWorld::default();
+        let [a, b, c, d, e] = [(); 5].map(|_| id_world.next());
+        let beam = BeamId::from_vertices;
+
+        // a triangle with a tail, so removing the tail also removes it's end vertex
+        let snapshot = SerializedGraph {
+            vertices: vec![
+                (a, Position::ZERO, 1u8),
+                (b, Position::X, 2),
+                (c, Position::Y, 3),
+                (d, Position::Z, 4),
+            ],
+            beams: vec![
+                (beam(a, b), 10u32),
+                (beam(b, c), 20),
+                (beam(a, c), 25),
+                (beam(c, d), 30),
+            ],
+        };
+        let original = ShipFrame::new(snapshot.clone());
+
+        let updates = [
+            FrameUpdate::AddBeam {
+                vertex_a: a,
+                new_a: None,
+                vertex_b: e,
+                new_b: Some((Position::NEG_X, 5)),
+                beam_data: 40,
+            },
+            FrameUpdate::AddBeam {
+                vertex_a: b,
+                new_a: None,
+                vertex_b: d,
+                new_b: None,
+                beam_data: 50,
+            },
+            FrameUpdate::RemoveBeam {
+                id: beam(c, d),
+                recovered: None,
+            },
+            FrameUpdate::RemoveBeam {
+                id: beam(c, d),
+                recovered: Some(RemovedBeamData {
+                    beam_data: 30,
+                    removed_vertices: vec![(d, Position::Z, 4)],
+                }),
+            },
+            FrameUpdate::MoveVertex {
+                id: a,
+                position: Position::ONE,
+            },
+            FrameUpdate::UpdateBeamData {
+                id: beam(a, b),
+                beam_data: 99,
+            },
+            FrameUpdate::UpdateVertexData {
+                id: b,
+                vertex_data: 9,
+            },
+        ];
+
+        for update in updates {
+            let mut client = ShipFrame::new(snapshot.clone());
+
+            let context = client.apply_update_with_context(update.clone()).unwrap();
+            assert!(client.graph() != original.graph());
+
+            client.unapply_update(&update, &context).unwrap();
+            assert!(client.graph() == original.graph());
+            assert!(!client.needs_resync());
+        }
+    }
+
+    #[test]
+    fn predicted_removal_confirms_without_rebuild() {
+        let mut id_world = FrameIdWorld::default();
+        let mut server =
+            server::ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32);
+        let (down, up) = server.iter_beams().next().unwrap().0.vertices();
+        let end = server.add_beam_extend(&mut id_world, up, Position::X + Position::Y * 10., 1);
+        let FrameUpdate::AddBeam { vertex_b: tip, .. } = end else {
+            unreachable!()
+        };
+
+        let mut frame = PredictedFrame::new(ShipFrame::new(server.serialize()));
+        let prediction = frame
+            .predict(FrameUpdate::RemoveBeam {
+                id: BeamId::from_vertices(up, tip),
+                recovered: None,
+            })
+            .unwrap();
+
+        let mut removals = server.remove_beams_in_sphere(Position::X + Position::Y * 10., 1.);
+        assert_eq!(removals.len(), 1);
+        let update = removals.pop().unwrap();
+        assert!(matches!(
+            update,
+            FrameUpdate::RemoveBeam {
+                recovered: Some(_),
+                ..
+            }
+        ));
+
+        // a rebuild would replace the predicted frame, losing this edit that isn't a prediction
+        frame
+            .predicted
+            .apply_update(FrameUpdate::MoveVertex {
+                id: down,
+                position: Position::NEG_Y,
+            })
+            .unwrap();
+
+        frame.apply_confirmed(update, Some(prediction)).unwrap();
+
+        assert_eq!(frame.pending_count(), 0);
+        assert_eq!(
+            frame.predicted().get_vertex(down).unwrap().position(),
+            Position::NEG_Y
+        );
+        assert_eq!(frame.predicted().beam_count(), 1);
     }
 }
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+
+use crate::{graph::Graph, server::ShipFrame, Position};
+
+/// Half the length of the lines of the cross drawn at each vertex.
+const VERTEX_CROSS_SIZE: f32 = 0.1;
+
+/// Draws a line for every beam and a small cross at every vertex.
+pub fn draw_graph<B, V>(gizmos: &mut Gizmos, graph: &Graph<B, V>, color: Color) {
+    for (id, _) in graph.iter_beams() {
+        let Some((down, up)) = graph.beam_endpoints(id) else {
+            continue;
+        };
+
+        gizmos.line(to_vec3(down), to_vec3(up), color);
+    }
+
+    for (_, vertex) in graph.iter_vertices() {
+        let position = to_vec3(vertex.position());
+
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let offset = axis * VERTEX_CROSS_SIZE;
+            gizmos.line(position - offset, position + offset, color);
+        }
+    }
+}
+
+/// Same as [draw_graph] for a server frame.
+pub fn draw_frame<B, V>(gizmos: &mut Gizmos, frame: &ShipFrame<B, V>, color: Color) {
+    draw_graph(gizmos, frame.graph(), color);
+}
+
+#[cfg(not(feature = "f64"))]
+fn to_vec3(position: Position) -> Vec3 {
+    position
+}
+
+/// Gizmos are drawn in single precision.
+#[cfg(feature = "f64")]
+fn to_vec3(position: Position) -> Vec3 {
+    position.as_vec3()
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::{
+    graph::{AddBeamError, SplitError},
+    messages::{ApplyError, BatchApplyError, ExtendError, FormatError, ValidationError},
+    server::{MergeError, SplitBeamError},
+};
+
+/// Any error returned by this crate, for use with `?`.
+///
+/// Each fallible function returns it's own more specific error,
+/// which all convert into this one.
+#[derive(Debug)]
+pub enum Error {
+    AddBeam(AddBeamError),
+    Split(SplitError),
+    Validation(ValidationError),
+    Extend(ExtendError),
+    Format(FormatError),
+    Apply(ApplyError),
+    BatchApply(BatchApplyError),
+    Merge(MergeError),
+    SplitBeam(SplitBeamError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::AddBeam(error) => error.fmt(f),
+            Error::Split(error) => error.fmt(f),
+            Error::Validation(error) => error.fmt(f),
+            Error::Extend(error) => error.fmt(f),
+            Error::Format(error) => error.fmt(f),
+            Error::Apply(error) => error.fmt(f),
+            Error::BatchApply(error) => error.fmt(f),
+            Error::Merge(error) => error.fmt(f),
+            Error::SplitBeam(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::AddBeam(error) => error.source(),
+            Error::Split(error) => error.source(),
+            Error::Validation(error) => error.source(),
+            Error::Extend(error) => error.source(),
+            Error::Format(error) => error.source(),
+            Error::Apply(error) => error.source(),
+            Error::BatchApply(error) => error.source(),
+            Error::Merge(error) => error.source(),
+            Error::SplitBeam(error) => error.source(),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($variant:ident($error:ty)),* $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(error: $error) -> Self {
+                    Error::$variant(error)
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(
+    AddBeam(AddBeamError),
+    Split(SplitError),
+    Validation(ValidationError),
+    Extend(ExtendError),
+    Format(FormatError),
+    Apply(ApplyError),
+    BatchApply(BatchApplyError),
+    Merge(MergeError),
+    SplitBeam(SplitBeamError),
+);
@@ -1,20 +1,99 @@
-use bevy::math::Vec3;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+    fmt,
+    hash::{Hash, Hasher},
+    ops::ControlFlow,
+};
+
+use bevy::{
+    transform::components::Transform,
+    utils::{HashMap, HashSet},
+};
 use indexmap::IndexMap;
 
-use crate::{BeamDirection, BeamEnd, BeamId, VertexId};
+use crate::{
+    messages::SerializedGraph, BeamDirection, BeamEnd, BeamId, Matrix, Position, Scalar, VertexId,
+};
 
 /// The core data structure used by the server and client.
-pub struct Graph<B> {
-    pub(crate) vertices: IndexMap<VertexId, Vertex>,
+///
+/// Vertices and beams are iterated in the order they were inserted,
+/// removing one doesn't change the order of the others.
+/// Re-inserting a removed vertex or beam puts it at the end.
+///
+/// Keeping the order means removing is linear in the size of the graph,
+/// use [Graph::retain_beams] to remove many beams at once.
+///
+/// Each vertex can carry data of type `V` alongside it's position, which defaults to nothing.
+pub struct Graph<B, V = ()> {
+    pub(crate) vertices: IndexMap<VertexId, Vertex<V>>,
     pub(crate) beams: IndexMap<BeamId, B>,
 }
 
-pub struct Vertex {
-    pub(crate) position: Vec3,
+pub struct Vertex<V = ()> {
+    pub(crate) position: Position,
+    pub(crate) data: V,
     pub(crate) connections: Vec<BeamEnd>,
 }
 
-impl<B> Default for Graph<B> {
+/// The reasons a beam can't be inserted into a [Graph].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddBeamError {
+    /// Both ends of the beam are the same vertex.
+    SelfLoop,
+    /// A new vertex was provided that is already in the graph.
+    VertexAlreadyExists(VertexId),
+    /// An existing vertex was referenced that isn't in the graph.
+    MissingVertex(VertexId),
+    /// The two vertices are already connected.
+    DuplicateBeam(BeamId),
+    /// A new vertex's position has a NaN or infinite component.
+    NonFinitePosition(VertexId),
+    /// A server frame is at it's beam limit, see [crate::server::ShipFrame::with_limit].
+    LimitExceeded,
+}
+
+/// The reasons a set of vertices can't be split off from a [Graph].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitError {
+    /// A vertex in the set isn't in the graph.
+    MissingVertex(VertexId),
+    /// A beam connects a vertex in the set to one outside of it.
+    CrossingBeam(BeamId),
+}
+
+impl fmt::Display for AddBeamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddBeamError::SelfLoop => write!(f, "beam connects a vertex to itself"),
+            AddBeamError::VertexAlreadyExists(id) => write!(f, "vertex {:?} already exists", id),
+            AddBeamError::MissingVertex(id) => write!(f, "vertex {:?} doesn't exist", id),
+            AddBeamError::DuplicateBeam(id) => write!(f, "beam {:?} already exists", id),
+            AddBeamError::NonFinitePosition(id) => {
+                write!(f, "vertex {:?} has a non finite position", id)
+            }
+            AddBeamError::LimitExceeded => write!(f, "frame is at it's beam limit"),
+        }
+    }
+}
+
+impl std::error::Error for AddBeamError {}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SplitError::MissingVertex(id) => write!(f, "vertex {:?} doesn't exist", id),
+            SplitError::CrossingBeam(id) => {
+                write!(f, "beam {:?} crosses the edge of the split", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+impl<B, V> Default for Graph<B, V> {
     fn default() -> Self {
         Graph {
             vertices: IndexMap::new(),
@@ -23,57 +102,116 @@ impl<B> Default for Graph<B> {
     }
 }
 
-impl<B> Graph<B> {
+impl<B, V> Graph<B, V> {
+    /// Creates an empty graph with space for at least `vertices` vertices and `beams` beams.
+    pub fn with_capacity(vertices: usize, beams: usize) -> Self {
+        Graph {
+            vertices: IndexMap::with_capacity(vertices),
+            beams: IndexMap::with_capacity(beams),
+        }
+    }
+
     /// Inserts a beam between either existing or new vertices.
     ///
     /// If one end of the beam is connecting to an existing vertex, provide it's position as `None`
-    /// Provide `Some` to insert a new vertex.
+    /// Provide `Some` to insert a new vertex, which gets the default vertex data.
     ///
-    /// Panics if inserting an existing vertex or if an exisiting vertex isn't in the graph.
+    /// The graph is left unmodified if an error is returned.
     pub fn add_beam(
         &mut self,
         vertex_a: VertexId,
-        position_a: Option<Vec3>,
+        position_a: Option<Position>,
         vertex_b: VertexId,
-        position_b: Option<Vec3>,
+        position_b: Option<Position>,
         beam_data: B,
-    ) {
-        let (down_id, down_position, up_id, up_position) = match vertex_a.cmp(&vertex_b) {
-            std::cmp::Ordering::Equal => {
-                panic!("Tried to insert a beam between a vertex and itself.")
-            }
-            std::cmp::Ordering::Less => (vertex_a, position_a, vertex_b, position_b),
-            std::cmp::Ordering::Greater => (vertex_b, position_b, vertex_a, position_a),
+    ) -> Result<(), AddBeamError>
+    where
+        V: Default,
+    {
+        self.add_beam_with_data(
+            vertex_a,
+            position_a.map(|position| (position, V::default())),
+            vertex_b,
+            position_b.map(|position| (position, V::default())),
+            beam_data,
+        )
+    }
+
+    /// Same as [Graph::add_beam] but new vertices are given their position and data.
+    ///
+    /// The graph is left unmodified if an error is returned.
+    pub fn add_beam_with_data(
+        &mut self,
+        vertex_a: VertexId,
+        new_a: Option<(Position, V)>,
+        vertex_b: VertexId,
+        new_b: Option<(Position, V)>,
+        beam_data: B,
+    ) -> Result<(), AddBeamError> {
+        let (down_id, down_new, up_id, up_new) = match vertex_a.cmp(&vertex_b) {
+            std::cmp::Ordering::Equal => return Err(AddBeamError::SelfLoop),
+            std::cmp::Ordering::Less => (vertex_a, new_a, vertex_b, new_b),
+            std::cmp::Ordering::Greater => (vertex_b, new_b, vertex_a, new_a),
         };
 
         let beam_id = BeamId::from_vertices(down_id, up_id);
 
-        for (id, position, beam_end) in [
-            (down_id, down_position, BeamDirection::Down),
-            (up_id, up_position, BeamDirection::Up),
+        for (id, new) in [(down_id, &down_new), (up_id, &up_new)] {
+            match (new, self.vertices.contains_key(&id)) {
+                (Some(_), true) => return Err(AddBeamError::VertexAlreadyExists(id)),
+                (None, false) => return Err(AddBeamError::MissingVertex(id)),
+                (Some((position, _)), false) if !position.is_finite() => {
+                    return Err(AddBeamError::NonFinitePosition(id))
+                }
+                _ => (),
+            }
+        }
+
+        if self.beams.contains_key(&beam_id) {
+            return Err(AddBeamError::DuplicateBeam(beam_id));
+        }
+
+        for (id, new, beam_end) in [
+            (down_id, down_new, BeamDirection::Down),
+            (up_id, up_new, BeamDirection::Up),
         ] {
-            if let Some(position) = position {
-                let None = self.vertices.insert(
+            let connection = BeamEnd { beam_id, beam_end };
+
+            if let Some((position, data)) = new {
+                self.vertices.insert(
                     id,
                     Vertex {
                         position,
-                        connections: vec![BeamEnd { beam_id, beam_end }],
+                        data,
+                        connections: vec![connection],
                     },
-                ) else {
-                    panic!("Tried to insert a vertex twice.");
-                };
-            } else {
-                let Some(vertex) = self.vertices.get_mut(&id) else {
-                    panic!("Tried to connect a beam to a vertex that doesn't exist.");
-                };
-
-                vertex.connections.push(BeamEnd { beam_id, beam_end });
+                );
+            } else if let Some(vertex) = self.vertices.get_mut(&id) {
+                vertex.connections.push(connection);
             }
         }
 
-        let None = self.beams.insert(beam_id, beam_data) else {
-            panic!("Tried to insert a beam twice.");
-        };
+        self.beams.insert(beam_id, beam_data);
+
+        Ok(())
+    }
+
+    /// Same as [Graph::add_beam] but for input that is already known to be valid.
+    ///
+    /// Panics if inserting an existing vertex or if an exisiting vertex isn't in the graph.
+    pub fn add_beam_unchecked(
+        &mut self,
+        vertex_a: VertexId,
+        position_a: Option<Position>,
+        vertex_b: VertexId,
+        position_b: Option<Position>,
+        beam_data: B,
+    ) where
+        V: Default,
+    {
+        if let Err(error) = self.add_beam(vertex_a, position_a, vertex_b, position_b, beam_data) {
+            panic!("Tried to insert an invalid beam: {:?}", error);
+        }
     }
 
     /// Removes a beam, removing it's vertices from the graph
@@ -81,7 +219,7 @@ impl<B> Graph<B> {
     ///
     /// Panics if the beam is not in the graph
     pub fn remove_beam(&mut self, beam: BeamId) -> B {
-        let Some(beam_data) = self.beams.swap_remove(&beam) else {
+        let Some(beam_data) = self.beams.shift_remove(&beam) else {
             panic!("Tried to remove a beam that doesn't exist.");
         };
 
@@ -101,17 +239,116 @@ impl<B> Graph<B> {
             vertex.connections.remove(index);
 
             if vertex.connections.is_empty() {
-                self.vertices.swap_remove(&id);
+                self.vertices.shift_remove(&id);
             }
         }
 
         beam_data
     }
 
-    pub fn get_vertex(&self, vertex_id: VertexId) -> Option<&Vertex> {
+    /// Same as [Graph::remove_beam] for the beam between two vertices,
+    /// returning `None` instead of panicking if they aren't connected.
+    pub fn remove_beam_between(&mut self, a: VertexId, b: VertexId) -> Option<B> {
+        let beam_id = BeamId::try_from_vertices(a, b)?;
+
+        if !self.beams.contains_key(&beam_id) {
+            return None;
+        }
+
+        Some(self.remove_beam(beam_id))
+    }
+
+    /// Removes a vertex along with every beam connected to it,
+    /// returning the removed beams.
+    ///
+    /// Neighboring vertices are removed if they are left without connections.
+    ///
+    /// Panics if the vertex is not in the graph.
+    pub fn remove_vertex(&mut self, id: VertexId) -> Vec<(BeamId, B)> {
+        let Some(vertex) = self.vertices.get(&id) else {
+            panic!("Tried to remove a vertex that doesn't exist.");
+        };
+
+        let beam_ids: Vec<BeamId> = vertex
+            .connections
+            .iter()
+            .map(|connection| connection.beam_id)
+            .collect();
+
+        let removed = beam_ids
+            .into_iter()
+            .map(|beam_id| (beam_id, self.remove_beam(beam_id)))
+            .collect();
+
+        // a vertex without connections isn't removed by `remove_beam`
+        self.vertices.shift_remove(&id);
+
+        removed
+    }
+
+    /// Removes every beam for which `keep` returns false, returning the removed beams.
+    ///
+    /// Like [Graph::remove_beam] vertices left without connections are removed.
+    pub fn retain_beams<F: FnMut(BeamId, &B) -> bool>(&mut self, mut keep: F) -> Vec<(BeamId, B)> {
+        let mut removed = Vec::new();
+        let mut kept = IndexMap::with_capacity(self.beams.len());
+
+        for (id, beam_data) in std::mem::take(&mut self.beams) {
+            if keep(id, &beam_data) {
+                kept.insert(id, beam_data);
+            } else {
+                removed.push((id, beam_data));
+            }
+        }
+
+        self.beams = kept;
+
+        if !removed.is_empty() {
+            self.vertices.retain(|_, vertex| {
+                vertex
+                    .connections
+                    .retain(|connection| self.beams.contains_key(&connection.beam_id));
+                !vertex.connections.is_empty()
+            });
+        }
+
+        removed
+    }
+
+    /// Moves an existing vertex.
+    ///
+    /// Panics if the vertex is not in the graph or the position isn't finite.
+    pub fn set_vertex_position(&mut self, id: VertexId, position: Position) {
+        let Some(vertex) = self.get_vertex_mut(id) else {
+            panic!("Tried to move a vertex that doesn't exist.");
+        };
+
+        vertex.set_position(position);
+    }
+
+    /// Removes all vertices and beams, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.beams.clear();
+    }
+
+    pub fn contains_vertex(&self, vertex_id: VertexId) -> bool {
+        self.vertices.contains_key(&vertex_id)
+    }
+
+    pub fn contains_beam(&self, beam_id: BeamId) -> bool {
+        self.beams.contains_key(&beam_id)
+    }
+
+    pub fn get_vertex(&self, vertex_id: VertexId) -> Option<&Vertex<V>> {
         self.vertices.get(&vertex_id)
     }
 
+    /// Only the vertex's position and data can be changed, it's connections are managed by the graph.
+    pub fn get_vertex_mut(&mut self, vertex_id: VertexId) -> Option<&mut Vertex<V>> {
+        self.vertices.get_mut(&vertex_id)
+    }
+
     pub fn get_beam(&self, beam_id: BeamId) -> Option<&B> {
         self.beams.get(&beam_id)
     }
@@ -120,28 +357,1532 @@ impl<B> Graph<B> {
         self.beams.get_mut(&beam_id)
     }
 
-    pub fn iter_vertices(&self) -> impl Iterator<Item = (VertexId, &Vertex)> {
+    /// Finds the beam connecting two vertices, if there is one.
+    pub fn beam_between(&self, a: VertexId, b: VertexId) -> Option<(BeamId, &B)> {
+        let beam_id = BeamId::from_vertices(a, b);
+        self.beams
+            .get(&beam_id)
+            .map(|beam_data| (beam_id, beam_data))
+    }
+
+    /// If a beam connects two vertices.
+    pub fn are_connected(&self, a: VertexId, b: VertexId) -> bool {
+        self.beam_between(a, b).is_some()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn beam_count(&self) -> usize {
+        self.beams.len()
+    }
+
+    /// Iterates over vertices in insertion order.
+    pub fn iter_vertices(&self) -> impl Iterator<Item = (VertexId, &Vertex<V>)> {
         self.vertices.iter().map(|(id, vertex)| (*id, vertex))
     }
-}
 
-impl Vertex {
-    pub fn position(&self) -> Vec3 {
-        self.position
+    /// Iterates over the position of every vertex, in the same order as [Graph::iter_vertices].
+    ///
+    /// Positions are stored alongside each vertex's connections so they can't be borrowed as a slice.
+    pub fn positions(&self) -> impl ExactSizeIterator<Item = Position> + '_ {
+        self.vertices.values().map(|vertex| vertex.position)
     }
 
-    pub fn connections(&self) -> &[BeamEnd] {
-        self.connections.as_slice()
+    /// Iterates over beams in insertion order.
+    pub fn iter_beams(&self) -> impl Iterator<Item = (BeamId, &B)> {
+        self.beams.iter().map(|(id, beam_data)| (*id, beam_data))
+    }
+
+    /// The position of a vertex in [Graph::iter_vertices], eg. to find it's slot in a GPU buffer.
+    ///
+    /// Indices only change when vertices are removed, which shifts every later vertex down by one.
+    pub fn vertex_index(&self, id: VertexId) -> Option<usize> {
+        self.vertices.get_index_of(&id)
+    }
+
+    /// The position of a beam in [Graph::iter_beams], see [Graph::vertex_index].
+    pub fn beam_index(&self, id: BeamId) -> Option<usize> {
+        self.beams.get_index_of(&id)
+    }
+
+    /// Iterates over beams in insertion order along with the positions of their down and up vertices,
+    /// eg. for drawing them.
+    pub fn iter_beams_with_positions(
+        &self,
+    ) -> impl Iterator<Item = (BeamId, Position, Position, &B)> {
+        self.beams.iter().map(|(id, beam_data)| {
+            (
+                *id,
+                self.vertices[&id.down_vertex()].position,
+                self.vertices[&id.up_vertex()].position,
+                beam_data,
+            )
+        })
+    }
+
+    /// Groups the beams by a key of their data, eg. their material,
+    /// with the beams of each group in insertion order.
+    ///
+    /// The index is a snapshot that has to be rebuilt after the graph changes.
+    /// The graph doesn't keep one up to date itself as beam data can be changed in place
+    /// through [Graph::get_beam_mut], which the graph can't see.
+    pub fn build_index<K: Hash + Eq>(&self, key: impl Fn(&B) -> K) -> HashMap<K, Vec<BeamId>> {
+        let mut index: HashMap<K, Vec<BeamId>> = HashMap::new();
+
+        for (&id, beam_data) in self.beams.iter() {
+            index.entry(key(beam_data)).or_default().push(id);
+        }
+
+        index
+    }
+
+    /// Same as [SerializedGraph::from] but moves the beam and vertex data out instead of cloning it.
+    pub fn into_serialized(self) -> SerializedGraph<B, V> {
+        let mut serialized = SerializedGraph {
+            vertices: self
+                .vertices
+                .into_iter()
+                .map(|(id, vertex)| (id, vertex.position, vertex.data))
+                .collect(),
+            beams: self.beams.into_iter().collect(),
+        };
+
+        serialized.vertices.sort_unstable_by_key(|&(id, ..)| id);
+        serialized.beams.sort_unstable_by_key(|&(id, _)| id);
+
+        serialized
+    }
+
+    /// Converts the data of every beam, keeping the vertices, topology and insertion order.
+    pub fn map_beam_data<C>(self, mut f: impl FnMut(BeamId, B) -> C) -> Graph<C, V> {
+        Graph {
+            vertices: self.vertices,
+            beams: self
+                .beams
+                .into_iter()
+                .map(|(id, beam_data)| (id, f(id, beam_data)))
+                .collect(),
+        }
+    }
+
+    /// Same as [Graph::map_beam_data] but borrows the graph, producing a serialized copy,
+    /// eg. to export beams in a different format.
+    pub fn map_beam_data_ref<C>(&self, mut f: impl FnMut(BeamId, &B) -> C) -> SerializedGraph<C, V>
+    where
+        V: Clone,
+    {
+        let mut serialized = SerializedGraph {
+            vertices: self
+                .vertices
+                .iter()
+                .map(|(&id, vertex)| (id, vertex.position, vertex.data.clone()))
+                .collect(),
+            beams: self
+                .beams
+                .iter()
+                .map(|(&id, beam_data)| (id, f(id, beam_data)))
+                .collect(),
+        };
+
+        serialized.vertices.sort_unstable_by_key(|&(id, ..)| id);
+        serialized.beams.sort_unstable_by_key(|&(id, _)| id);
+
+        serialized
+    }
+
+    /// The number of beams connected to a vertex.
+    pub fn degree(&self, id: VertexId) -> Option<usize> {
+        self.vertices
+            .get(&id)
+            .map(|vertex| vertex.connections.len())
+    }
+
+    /// Iterates over the vertices that have exactly one beam connected to them.
+    pub fn leaves(&self) -> impl Iterator<Item = VertexId> + '_ {
+        self.vertices
+            .iter()
+            .filter(|(_, vertex)| vertex.connections.len() == 1)
+            .map(|(id, _)| *id)
+    }
+
+    /// Iterates over the vertices connected to a vertex
+    /// along with the data of the beam connecting them.
+    ///
+    /// Empty if the vertex is not in the graph.
+    pub fn neighbors(&self, id: VertexId) -> impl Iterator<Item = (VertexId, &B)> {
+        self.vertices
+            .get(&id)
+            .into_iter()
+            .flat_map(|vertex| vertex.connections.iter())
+            .filter_map(|connection| {
+                let beam_data = self.beams.get(&connection.beam_id)?;
+                Some((connection.far_vertex(), beam_data))
+            })
+    }
+
+    /// Iterates over the beams connected to a vertex,
+    /// along with which end of the beam the vertex is at.
+    ///
+    /// Empty if the vertex is not in the graph.
+    pub fn incident_beams(
+        &self,
+        id: VertexId,
+    ) -> impl Iterator<Item = (BeamId, BeamDirection, &B)> {
+        self.vertices
+            .get(&id)
+            .into_iter()
+            .flat_map(|vertex| vertex.connections.iter())
+            .filter_map(|connection| {
+                let beam_data = self.beams.get(&connection.beam_id)?;
+                Some((connection.beam_id, connection.beam_end, beam_data))
+            })
     }
 }
 
-impl<B> std::fmt::Debug for Graph<B> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Graph {{ vertices: {}, beams: {} }}",
-            self.vertices.len(),
-            self.beams.len()
-        )
+impl<B, V> Graph<B, V> {
+    /// Iterates breadth first over every vertex reachable from `start`,
+    /// along with the number of beams between it and `start`.
+    ///
+    /// Empty if `start` is not in the graph.
+    pub fn bfs(&self, start: VertexId) -> impl Iterator<Item = (VertexId, u32)> + '_ {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        if self.vertices.contains_key(&start) {
+            visited.insert(start);
+            queue.push_back((start, 0));
+        }
+
+        std::iter::from_fn(move || {
+            let (id, distance) = queue.pop_front()?;
+
+            for connection in self.vertices[&id].connections.iter() {
+                let neighbor = connection.far_vertex();
+
+                if visited.insert(neighbor) {
+                    queue.push_back((neighbor, distance + 1));
+                }
+            }
+
+            Some((id, distance))
+        })
+    }
+
+    /// Visits vertices in the same order as [Graph::bfs] until `visit` returns [ControlFlow::Break],
+    /// eg. to stop at the first vertex that matches a query.
+    ///
+    /// Returns [ControlFlow::Break] if the search was stopped early.
+    pub fn bfs_visit(
+        &self,
+        start: VertexId,
+        mut visit: impl FnMut(VertexId, u32) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        for (id, distance) in self.bfs(start) {
+            visit(id, distance)?;
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Iterates depth first over every vertex reachable from `start`.
+    ///
+    /// Empty if `start` is not in the graph.
+    pub fn dfs(&self, start: VertexId) -> impl Iterator<Item = VertexId> + '_ {
+        let mut visited = HashSet::new();
+        let mut stack = Vec::new();
+
+        if self.vertices.contains_key(&start) {
+            stack.push(start);
+        }
+
+        std::iter::from_fn(move || loop {
+            let id = stack.pop()?;
+
+            if !visited.insert(id) {
+                continue;
+            }
+
+            stack.extend(
+                self.vertices[&id]
+                    .connections
+                    .iter()
+                    .rev()
+                    .map(BeamEnd::far_vertex)
+                    .filter(|neighbor| !visited.contains(neighbor)),
+            );
+
+            return Some(id);
+        })
+    }
+
+    /// Same as [Graph::remove_beam], but also checks if the graph was split in two.
+    ///
+    /// If it was, the vertices of the smaller of the two new components are returned.
+    /// Only the two components are searched, stopping when the smaller one is exhausted.
+    ///
+    /// Panics if the beam is not in the graph
+    pub fn remove_beam_split(&mut self, beam: BeamId) -> (B, Option<Vec<VertexId>>) {
+        let beam_data = self.remove_beam(beam);
+
+        let (down, up) = beam.vertices();
+
+        // a vertex removed along with the beam doesn't form a new component
+        if !self.vertices.contains_key(&down) || !self.vertices.contains_key(&up) {
+            return (beam_data, None);
+        }
+
+        let mut searches = [self.bfs(down), self.bfs(up)];
+        let mut found = [Vec::new(), Vec::new()];
+        let mut seen = [HashSet::new(), HashSet::new()];
+
+        let split = 'search: loop {
+            for side in 0..2 {
+                let Some((id, _)) = searches[side].next() else {
+                    break 'search Some(std::mem::take(&mut found[side]));
+                };
+
+                if seen[1 - side].contains(&id) {
+                    break 'search None;
+                }
+
+                seen[side].insert(id);
+                found[side].push(id);
+            }
+        };
+
+        (beam_data, split)
+    }
+
+    /// Moves a set of vertices and their beams into a new graph, keeping their ids.
+    ///
+    /// The set has to be made up of whole components,
+    /// no beam can connect a vertex in the set to one outside of it.
+    ///
+    /// The graph is left unmodified if an error is returned.
+    pub fn split_off(&mut self, vertices: &[VertexId]) -> Result<Graph<B, V>, SplitError> {
+        let set: HashSet<VertexId> = vertices.iter().copied().collect();
+
+        for &id in vertices {
+            let Some(vertex) = self.vertices.get(&id) else {
+                return Err(SplitError::MissingVertex(id));
+            };
+
+            for connection in vertex.connections.iter() {
+                if !set.contains(&connection.far_vertex()) {
+                    return Err(SplitError::CrossingBeam(connection.beam_id));
+                }
+            }
+        }
+
+        let mut split = Graph::default();
+
+        for &id in vertices {
+            let Some(vertex) = self.vertices.shift_remove(&id) else {
+                continue;
+            };
+
+            for connection in vertex.connections.iter() {
+                if let Some(beam_data) = self.beams.shift_remove(&connection.beam_id) {
+                    split.beams.insert(connection.beam_id, beam_data);
+                }
+            }
+
+            split.vertices.insert(id, vertex);
+        }
+
+        Ok(split)
+    }
+
+    /// Copies the beams `keep` returns true for along with the vertices they connect.
+    ///
+    /// Like [SerializedGraph::from] the result is sorted by id.
+    pub fn subgraph<F: Fn(BeamId, &B) -> bool>(&self, keep: F) -> SerializedGraph<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let mut subgraph = SerializedGraph::default();
+        let mut vertices = HashSet::new();
+
+        for (&id, beam_data) in self.beams.iter() {
+            if keep(id, beam_data) {
+                vertices.insert(id.down_vertex());
+                vertices.insert(id.up_vertex());
+                subgraph.beams.push((id, beam_data.clone()));
+            }
+        }
+
+        for (&id, vertex) in self.vertices.iter() {
+            if vertices.contains(&id) {
+                subgraph
+                    .vertices
+                    .push((id, vertex.position, vertex.data.clone()));
+            }
+        }
+
+        subgraph.vertices.sort_unstable_by_key(|&(id, ..)| id);
+        subgraph.beams.sort_unstable_by_key(|&(id, _)| id);
+
+        subgraph
+    }
+
+    /// Finds the cheapest path between two vertices using Dijkstra's algorithm,
+    /// returning the vertices along it from `from` to `to`.
+    ///
+    /// `cost` gives the cost of traversing each beam, eg. [Graph::beam_length], and shouldn't be negative.
+    /// `None` if there is no path.
+    pub fn shortest_path(
+        &self,
+        from: VertexId,
+        to: VertexId,
+        cost: impl Fn(BeamId, &B) -> Scalar,
+    ) -> Option<Vec<VertexId>> {
+        if !self.vertices.contains_key(&from) || !self.vertices.contains_key(&to) {
+            return None;
+        }
+
+        let mut costs = HashMap::new();
+        let mut previous = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        costs.insert(from, 0.);
+        queue.push(PathCandidate {
+            cost: 0.,
+            vertex: from,
+        });
+
+        while let Some(PathCandidate {
+            cost: path_cost,
+            vertex,
+        }) = queue.pop()
+        {
+            if vertex == to {
+                break;
+            }
+
+            if costs.get(&vertex).is_some_and(|&best| path_cost > best) {
+                continue;
+            }
+
+            for connection in self.vertices[&vertex].connections.iter() {
+                let Some(beam_data) = self.beams.get(&connection.beam_id) else {
+                    continue;
+                };
+
+                let neighbor = connection.far_vertex();
+                let neighbor_cost = path_cost + cost(connection.beam_id, beam_data);
+
+                if costs
+                    .get(&neighbor)
+                    .is_none_or(|&best| neighbor_cost < best)
+                {
+                    costs.insert(neighbor, neighbor_cost);
+                    previous.insert(neighbor, vertex);
+                    queue.push(PathCandidate {
+                        cost: neighbor_cost,
+                        vertex: neighbor,
+                    });
+                }
+            }
+        }
+
+        if !costs.contains_key(&to) {
+            return None;
+        }
+
+        let mut path = vec![to];
+
+        while let Some(&vertex) = previous.get(path.last()?) {
+            path.push(vertex);
+        }
+
+        path.reverse();
+        Some(path)
+    }
+
+    /// If the graph is connected and has no cycles.
+    ///
+    /// An empty graph isn't a tree.
+    pub fn is_tree(&self) -> bool {
+        let Some(&start) = self.vertices.keys().next() else {
+            return false;
+        };
+
+        self.beams.len() + 1 == self.vertices.len()
+            && self.bfs(start).count() == self.vertices.len()
+    }
+
+    /// Finds the beams of a cycle if there is one.
+    ///
+    /// Beams are added to a spanning forest in order until one connects two vertices
+    /// that are already connected, the cycle is that beam and the path between it's ends.
+    pub fn find_cycle(&self) -> Option<Vec<BeamId>> {
+        let mut roots = HashMap::new();
+        let mut forest: HashMap<VertexId, Vec<(VertexId, BeamId)>> = HashMap::new();
+
+        for &beam_id in self.beams.keys() {
+            let (down, up) = beam_id.vertices();
+
+            let down_root = find_root(&mut roots, down);
+            let up_root = find_root(&mut roots, up);
+
+            if down_root != up_root {
+                roots.insert(down_root, up_root);
+                forest.entry(down).or_default().push((up, beam_id));
+                forest.entry(up).or_default().push((down, beam_id));
+                continue;
+            }
+
+            // search the forest for the path back from `up` to `down`
+            let mut previous = HashMap::new();
+            let mut queue = VecDeque::from([down]);
+            previous.insert(down, None);
+
+            while let Some(vertex) = queue.pop_front() {
+                if vertex == up {
+                    break;
+                }
+
+                for &(neighbor, edge) in forest.get(&vertex).into_iter().flatten() {
+                    if !previous.contains_key(&neighbor) {
+                        previous.insert(neighbor, Some((vertex, edge)));
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+
+            let mut cycle = vec![beam_id];
+            let mut vertex = up;
+
+            while let Some(&Some((next, edge))) = previous.get(&vertex) {
+                cycle.push(edge);
+                vertex = next;
+            }
+
+            return Some(cycle);
+        }
+
+        None
+    }
+
+    /// Finds the vertices whose removal would split their component.
+    pub fn articulation_points(&self) -> Vec<VertexId> {
+        self.low_link().0
+    }
+
+    /// Finds the beams whose removal would split their component.
+    pub fn bridges(&self) -> Vec<BeamId> {
+        self.low_link().1
+    }
+
+    /// Finds articulation points and bridges with Tarjan's low link algorithm.
+    ///
+    /// The depth first search uses an explicit stack to handle very large graphs.
+    fn low_link(&self) -> (Vec<VertexId>, Vec<BeamId>) {
+        let mut discovered = HashMap::new();
+        let mut low = HashMap::new();
+        let mut time = 0u32;
+
+        let mut articulation_points = Vec::new();
+        let mut is_articulation_point = HashSet::new();
+        let mut bridges = Vec::new();
+
+        for &root in self.vertices.keys() {
+            if discovered.contains_key(&root) {
+                continue;
+            }
+
+            discovered.insert(root, time);
+            low.insert(root, time);
+            time += 1;
+
+            let mut root_children = 0;
+            // vertex, beam to it's parent, index of the next connection to visit
+            let mut stack: Vec<(VertexId, Option<BeamId>, usize)> = vec![(root, None, 0)];
+
+            while let Some(top) = stack.last_mut() {
+                let (vertex, parent_beam, index) = *top;
+                let connections = &self.vertices[&vertex].connections;
+
+                if let Some(connection) = connections.get(index) {
+                    top.2 += 1;
+
+                    if Some(connection.beam_id) == parent_beam {
+                        continue;
+                    }
+
+                    let neighbor = connection.far_vertex();
+
+                    if let Some(&neighbor_discovered) = discovered.get(&neighbor) {
+                        let vertex_low = low.get_mut(&vertex).unwrap();
+                        *vertex_low = (*vertex_low).min(neighbor_discovered);
+                    } else {
+                        discovered.insert(neighbor, time);
+                        low.insert(neighbor, time);
+                        time += 1;
+                        stack.push((neighbor, Some(connection.beam_id), 0));
+                    }
+
+                    continue;
+                }
+
+                stack.pop();
+
+                let Some(&(parent, ..)) = stack.last() else {
+                    continue;
+                };
+
+                let vertex_low = low[&vertex];
+                let parent_low = low.get_mut(&parent).unwrap();
+                *parent_low = (*parent_low).min(vertex_low);
+
+                if vertex_low > discovered[&parent] {
+                    bridges.extend(parent_beam);
+                }
+
+                if parent == root {
+                    root_children += 1;
+                } else if vertex_low >= discovered[&parent] && is_articulation_point.insert(parent)
+                {
+                    articulation_points.push(parent);
+                }
+            }
+
+            if root_children > 1 {
+                articulation_points.push(root);
+            }
+        }
+
+        (articulation_points, bridges)
+    }
+
+    /// Partitions the vertices into groups that are connected by beams.
+    ///
+    /// The order of the groups and their vertices is deterministic for a given graph.
+    pub fn connected_components(&self) -> Vec<Vec<VertexId>> {
+        let mut visited = HashSet::new();
+        let mut components = Vec::new();
+
+        for &id in self.vertices.keys() {
+            if visited.contains(&id) {
+                continue;
+            }
+
+            let component: Vec<VertexId> = self.bfs(id).map(|(id, _)| id).collect();
+            visited.extend(component.iter().copied());
+            components.push(component);
+        }
+
+        components
+    }
+}
+
+impl<B, V> Graph<B, V> {
+    /// The positions of the down and up ends of a beam.
+    pub fn beam_endpoints(&self, id: BeamId) -> Option<(Position, Position)> {
+        if !self.beams.contains_key(&id) {
+            return None;
+        }
+
+        let down = self.vertices.get(&id.down_vertex())?;
+        let up = self.vertices.get(&id.up_vertex())?;
+
+        Some((down.position, up.position))
+    }
+
+    /// The distance between the two ends of a beam.
+    pub fn beam_length(&self, id: BeamId) -> Option<Scalar> {
+        self.beam_vector(id).map(Position::length)
+    }
+
+    /// The vector from the down end of a beam to it's up end.
+    pub fn beam_vector(&self, id: BeamId) -> Option<Position> {
+        let (down, up) = self.beam_endpoints(id)?;
+        Some(up - down)
+    }
+
+    /// The point halfway between the two ends of a beam.
+    pub fn beam_midpoint(&self, id: BeamId) -> Option<Position> {
+        let (down, up) = self.beam_endpoints(id)?;
+        Some(down.midpoint(up))
+    }
+
+    /// The summed length of every beam.
+    pub fn total_beam_length(&self) -> Scalar {
+        self.beams
+            .keys()
+            .filter_map(|&id| self.beam_length(id))
+            .sum()
+    }
+
+    /// The mass weighted average position of the beams,
+    /// with each beam's mass placed at it's midpoint.
+    ///
+    /// `None` if there are no beams or their total mass is zero.
+    pub fn center_of_mass(&self, mass_of: impl Fn(&B) -> Scalar) -> Option<Position> {
+        let mut total_mass = 0.;
+        let mut weighted_sum = Position::ZERO;
+
+        for (id, beam_data) in self.beams.iter() {
+            let Some((down, up)) = self.beam_endpoints(*id) else {
+                continue;
+            };
+
+            let mass = mass_of(beam_data);
+            total_mass += mass;
+            weighted_sum += (down + up) * 0.5 * mass;
+        }
+
+        (total_mass != 0.).then(|| weighted_sum / total_mass)
+    }
+
+    /// The moment of inertia tensor of the beams about a point, eg. the [Graph::center_of_mass],
+    /// with each beam treated as a thin rod of uniform density.
+    ///
+    /// A rod with mass `m` from `a` to `b`, relative to `about`, has points `r(t) = a + t d`
+    /// for `t` in `[0, 1]` where `d = b - a`. It's tensor is the integral of `m (|r|² I - r rᵀ) dt`,
+    /// which works out to the tensor of a point mass at the midpoint `c = a + d / 2`
+    /// plus the rod spinning about it's own middle:
+    ///
+    /// `m (|c|² I - c cᵀ) + m (|d|² I - d dᵀ) / 12`
+    ///
+    /// The first term alone is what [Graph::center_of_mass] assumes, so the second is the
+    /// correction for long beams. Rods have no thickness, so a rod has no inertia about it's own axis.
+    pub fn inertia_tensor(&self, mass_of: impl Fn(&B) -> Scalar, about: Position) -> Matrix {
+        let mut tensor = Matrix::ZERO;
+
+        for (id, beam_data) in self.beams.iter() {
+            let Some((down, up)) = self.beam_endpoints(*id) else {
+                continue;
+            };
+
+            let mass = mass_of(beam_data);
+            let midpoint = (down + up) * 0.5 - about;
+            let direction = up - down;
+
+            tensor += (point_inertia(midpoint) + point_inertia(direction) * (1. / 12.)) * mass;
+        }
+
+        tensor
+    }
+
+    /// The corners of the smallest axis aligned box containing every vertex, as `(min, max)`.
+    ///
+    /// `None` if there are no vertices.
+    pub fn bounds(&self) -> Option<(Position, Position)> {
+        let mut positions = self.vertices.values().map(|vertex| vertex.position);
+        let first = positions.next()?;
+
+        Some(positions.fold((first, first), |(min, max), position| {
+            (min.min(position), max.max(position))
+        }))
+    }
+
+    /// Hashes the graph's ids, positions and data, for comparing copies of a graph
+    /// on different machines, eg. to detect a client that is out of sync with the server.
+    ///
+    /// Vertices and beams are hashed in order of their ids, so the order of insertion doesn't matter.
+    /// Positions are rounded to the nearest multiple of [CHECKSUM_GRID] first.
+    ///
+    /// The result doesn't depend on the platform as long as the `Hash` implementations of
+    /// the beam and vertex data don't, unlike [std::hash::DefaultHasher] it won't change between versions of Rust.
+    pub fn checksum(&self) -> u64
+    where
+        B: Hash,
+        V: Hash,
+    {
+        let mut vertex_ids: Vec<VertexId> = self.vertices.keys().copied().collect();
+        let mut beam_ids: Vec<BeamId> = self.beams.keys().copied().collect();
+        vertex_ids.sort_unstable();
+        beam_ids.sort_unstable();
+
+        let mut hasher = StableHasher::default();
+
+        hasher.write_usize(vertex_ids.len());
+        for id in vertex_ids {
+            let vertex = &self.vertices[&id];
+
+            id.hash(&mut hasher);
+            for value in (vertex.position / CHECKSUM_GRID).round().to_array() {
+                hasher.write_i64(value as i64);
+            }
+            vertex.data.hash(&mut hasher);
+        }
+
+        hasher.write_usize(beam_ids.len());
+        for id in beam_ids {
+            id.hash(&mut hasher);
+            self.beams[&id].hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    /// Finds the closest vertex to a point, along with it's squared distance.
+    pub fn nearest_vertex(&self, point: Position) -> Option<(VertexId, Scalar)> {
+        // linear scan, can be replaced with a spatial index without changing the signature
+        self.vertices
+            .iter()
+            .map(|(&id, vertex)| (id, vertex.position.distance_squared(point)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    /// Finds pairs of beams that share a vertex and run along each other from it,
+    /// which usually means one of them is redundant, eg. a brace doubling a beam through a collinear vertex.
+    ///
+    /// Two beams overlap when the far end of the shorter one is within `epsilon` of the longer one.
+    /// Beams that leave the vertex in opposite directions don't overlap, and zero length beams are ignored.
+    ///
+    /// Each pair is reported once, ordered by the vertex they share and then by it's connections.
+    pub fn find_collinear_overlaps(&self, epsilon: Scalar) -> Vec<(BeamId, BeamId)> {
+        let mut overlaps = Vec::new();
+        let mut directions = Vec::new();
+
+        for vertex in self.vertices.values() {
+            directions.clear();
+            directions.extend(
+                vertex
+                    .connections
+                    .iter()
+                    .map(|connection| {
+                        let far = self.vertices[&connection.far_vertex()].position;
+                        (connection.beam_id, far - vertex.position)
+                    })
+                    .filter(|(_, vector)| *vector != Position::ZERO),
+            );
+
+            for (index, &(id_a, vector_a)) in directions.iter().enumerate() {
+                for &(id_b, vector_b) in directions[index + 1..].iter() {
+                    let (short, long) = if vector_a.length_squared() < vector_b.length_squared() {
+                        (vector_a, vector_b)
+                    } else {
+                        (vector_b, vector_a)
+                    };
+
+                    let direction = long.normalize();
+                    let along = short.dot(direction);
+
+                    if along > 0. && (short - direction * along).length() <= epsilon {
+                        overlaps.push((id_a, id_b));
+                    }
+                }
+            }
+        }
+
+        overlaps
+    }
+
+    /// Applies a transform to the position of every vertex, eg. to move a frame from local to world space.
+    pub fn transform(&mut self, transform: &Transform) {
+        for vertex in self.vertices.values_mut() {
+            vertex.position = transform_position(transform, vertex.position);
+        }
+    }
+
+    /// Same as [Graph::transform] but leaves the graph unmodified, returning a transformed copy.
+    ///
+    /// Like [SerializedGraph::from] the result is sorted by id.
+    pub fn transformed(&self, transform: &Transform) -> SerializedGraph<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let mut serialized = SerializedGraph::from(self);
+
+        for (_, position, _) in serialized.vertices.iter_mut() {
+            *position = transform_position(transform, *position);
+        }
+
+        serialized
+    }
+
+    /// Finds every beam that intersects a sphere,
+    /// including beams that only cross the sphere's boundary.
+    ///
+    /// A beam intersects if the closest point on it's segment to `center` is within `radius`,
+    /// so long beams passing through the sphere are found even if both ends are outside it.
+    /// For a beam from `a` to `b` the closest point is `a + t (b - a)`
+    /// with `t = ((center - a) · (b - a)) / |b - a|²` clamped to `0..=1`,
+    /// the projection of `center` onto the beam's line clamped to it's ends.
+    /// A zero length beam's closest point is `a`.
+    ///
+    /// Nothing is found if `radius` is negative or isn't finite.
+    pub fn beams_in_radius(&self, center: Position, radius: Scalar) -> Vec<BeamId> {
+        if !radius.is_finite() || radius < 0. {
+            return Vec::new();
+        }
+
+        // linear scan, can be replaced with a spatial index without changing the signature
+        self.beams
+            .keys()
+            .copied()
+            .filter(|&beam_id| {
+                let Some((a, b)) = self.beam_endpoints(beam_id) else {
+                    return false;
+                };
+
+                closest_point_on_segment(center, a, b).distance_squared(center) <= radius * radius
+            })
+            .collect()
+    }
+
+    /// Creates a simplified copy of the graph by collapsing every beam shorter than `min_length`,
+    /// merging it's two vertices into one at their midpoint.
+    /// This repeats until no beam is shorter than `min_length`.
+    ///
+    /// The merged vertex keeps the id and data of the beam's down vertex.
+    /// If a rewired beam would duplicate a beam already connected to that vertex it's dropped.
+    ///
+    /// Like [SerializedGraph::from] the result is sorted by id.
+    pub fn simplify(&self, min_length: Scalar) -> SerializedGraph<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let mut positions: HashMap<VertexId, Position> = self
+            .vertices
+            .iter()
+            .map(|(&id, vertex)| (id, vertex.position))
+            .collect();
+
+        let mut neighbors: HashMap<VertexId, Vec<VertexId>> = self
+            .vertices
+            .iter()
+            .map(|(&id, vertex)| {
+                (
+                    id,
+                    vertex.connections.iter().map(BeamEnd::far_vertex).collect(),
+                )
+            })
+            .collect();
+
+        let mut beams: HashMap<BeamId, B> = self
+            .beams
+            .iter()
+            .map(|(&id, beam_data)| (id, beam_data.clone()))
+            .collect();
+
+        let mut queue: VecDeque<BeamId> = self.beams.keys().copied().collect();
+        queue.make_contiguous().sort_unstable();
+
+        while let Some(beam_id) = queue.pop_front() {
+            if !beams.contains_key(&beam_id) {
+                continue;
+            }
+
+            let (down, up) = beam_id.vertices();
+
+            if positions[&down].distance(positions[&up]) >= min_length {
+                continue;
+            }
+
+            beams.remove(&beam_id);
+
+            let up_position = positions
+                .remove(&up)
+                .expect("Vertex should exist if beam exists.");
+            let down_position = positions
+                .get_mut(&down)
+                .expect("Vertex should exist if beam exists.");
+            *down_position = down_position.midpoint(up_position);
+
+            let up_neighbors = neighbors.remove(&up).unwrap_or_default();
+            let down_neighbors = neighbors
+                .get_mut(&down)
+                .expect("Vertex should exist if beam exists.");
+            down_neighbors.retain(|&neighbor| neighbor != up);
+
+            let mut rewired = Vec::new();
+
+            for other in up_neighbors {
+                if other == down {
+                    continue;
+                }
+
+                let beam_data = beams
+                    .remove(&BeamId::from_vertices(up, other))
+                    .expect("Beam should exist if vertices are connected.");
+
+                let other_neighbors = neighbors.get_mut(&other).expect("Neighbors should exist.");
+                other_neighbors.retain(|&neighbor| neighbor != up);
+
+                let new_id = BeamId::from_vertices(down, other);
+
+                if beams.contains_key(&new_id) {
+                    continue;
+                }
+
+                other_neighbors.push(down);
+                rewired.push(other);
+                beams.insert(new_id, beam_data);
+            }
+
+            let down_neighbors = neighbors
+                .get_mut(&down)
+                .expect("Vertex should exist if beam exists.");
+            down_neighbors.extend(rewired);
+
+            // every beam at the merged vertex changed length, so needs to be checked again
+            let mut changed: Vec<BeamId> = down_neighbors
+                .iter()
+                .map(|&neighbor| BeamId::from_vertices(down, neighbor))
+                .collect();
+            changed.sort_unstable();
+            queue.extend(changed);
+        }
+
+        let mut simplified = SerializedGraph {
+            vertices: positions
+                .into_iter()
+                .filter(|(id, _)| {
+                    neighbors
+                        .get(id)
+                        .is_some_and(|neighbors| !neighbors.is_empty())
+                })
+                .map(|(id, position)| (id, position, self.vertices[&id].data.clone()))
+                .collect(),
+            beams: beams.into_iter().collect(),
+        };
+
+        simplified.vertices.sort_unstable_by_key(|&(id, ..)| id);
+        simplified.beams.sort_unstable_by_key(|&(id, _)| id);
+
+        simplified
+    }
+
+    /// Merges vertices that are within `epsilon` of each other into one,
+    /// eg. to connect parts of an imported frame that should share a vertex.
+    ///
+    /// Merging is transitive, so a chain of close vertices becomes one vertex
+    /// even if it's ends are further apart than `epsilon`.
+    /// The merged vertex keeps the id, position and data of the vertex with the smallest id.
+    /// This is the oldest vertex only if ids aren't recycled, see [crate::ids::FrameIdAllocator].
+    /// Beams are moved to the merged vertex, dropping those that would connect it to itself
+    /// or duplicate another beam, and vertices left without connections are removed.
+    ///
+    /// Returns how many vertices were merged into another.
+    ///
+    /// Panics if `epsilon` isn't finite and positive.
+    pub fn weld_coincident(&mut self, epsilon: Scalar) -> usize {
+        assert!(
+            epsilon.is_finite() && epsilon > 0.,
+            "Tried to weld with an epsilon that isn't finite and positive."
+        );
+
+        // any two vertices within `epsilon` are in the same or neighboring cells
+        let cell_of = |position: Position| {
+            (position / epsilon)
+                .floor()
+                .to_array()
+                .map(|value| value as i64)
+        };
+
+        let mut cells: HashMap<[i64; 3], Vec<VertexId>> = HashMap::new();
+        let mut roots = HashMap::new();
+
+        for (&id, vertex) in self.vertices.iter() {
+            let [x, y, z] = cell_of(vertex.position);
+
+            for neighbor_cell in (-1..=1).flat_map(|dx| {
+                (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [x + dx, y + dy, z + dz]))
+            }) {
+                let Some(others) = cells.get(&neighbor_cell) else {
+                    continue;
+                };
+
+                for &other in others {
+                    if self.vertices[&other].position.distance(vertex.position) > epsilon {
+                        continue;
+                    }
+
+                    let root = find_root(&mut roots, id);
+                    let other_root = find_root(&mut roots, other);
+
+                    if root == other_root {
+                        continue;
+                    }
+
+                    // the smallest id survives, regardless of where it is in the graph
+                    roots.insert(root.max(other_root), root.min(other_root));
+                }
+            }
+
+            cells.entry([x, y, z]).or_default().push(id);
+        }
+
+        if roots.is_empty() {
+            return 0;
+        }
+
+        let merged: Vec<VertexId> = roots.keys().copied().collect();
+        let mut merged_into = HashMap::with_capacity(merged.len());
+
+        for &id in merged.iter() {
+            merged_into.insert(id, find_root(&mut roots, id));
+        }
+
+        let moved: Vec<BeamId> = self
+            .beams
+            .keys()
+            .filter(|id| {
+                merged_into.contains_key(&id.down_vertex())
+                    || merged_into.contains_key(&id.up_vertex())
+            })
+            .copied()
+            .collect();
+
+        let mut rewired = Vec::with_capacity(moved.len());
+
+        for id in moved {
+            let beam_data = self
+                .beams
+                .shift_remove(&id)
+                .expect("Beam should exist while rewiring.");
+
+            for vertex in [id.down_vertex(), id.up_vertex()] {
+                self.vertices[&vertex]
+                    .connections
+                    .retain(|connection| connection.beam_id != id);
+            }
+
+            let endpoint = |vertex| merged_into.get(&vertex).copied().unwrap_or(vertex);
+            rewired.push((
+                endpoint(id.down_vertex()),
+                endpoint(id.up_vertex()),
+                beam_data,
+            ));
+        }
+
+        for id in merged.iter() {
+            self.vertices.shift_remove(id);
+        }
+
+        for (vertex_a, vertex_b, beam_data) in rewired {
+            if vertex_a == vertex_b || self.are_connected(vertex_a, vertex_b) {
+                continue;
+            }
+
+            let beam_id = BeamId::from_vertices(vertex_a, vertex_b);
+
+            for (vertex, beam_end) in [
+                (beam_id.down_vertex(), BeamDirection::Down),
+                (beam_id.up_vertex(), BeamDirection::Up),
+            ] {
+                self.vertices[&vertex]
+                    .connections
+                    .push(BeamEnd { beam_id, beam_end });
+            }
+
+            self.beams.insert(beam_id, beam_data);
+        }
+
+        for root in merged_into.into_values() {
+            if self
+                .vertices
+                .get(&root)
+                .is_some_and(|vertex| vertex.connections.is_empty())
+            {
+                self.vertices.shift_remove(&root);
+            }
+        }
+
+        merged.len()
+    }
+}
+
+#[cfg(not(feature = "f64"))]
+fn transform_position(transform: &Transform, position: Position) -> Position {
+    transform.transform_point(position)
+}
+
+/// Same as [Transform::transform_point] but in double precision.
+#[cfg(feature = "f64")]
+fn transform_position(transform: &Transform, position: Position) -> Position {
+    transform.rotation.as_dquat() * (transform.scale.as_dvec3() * position)
+        + transform.translation.as_dvec3()
+}
+
+/// The size of the grid positions are rounded to by [Graph::checksum].
+pub const CHECKSUM_GRID: Scalar = 1. / 1024.;
+
+/// 64 bit FNV-1a, with integers written in little endian so it's the same on every platform.
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, value: u128) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_i16(&mut self, value: i16) {
+        self.write_u16(value as u16);
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.write_u32(value as u32);
+    }
+
+    fn write_i64(&mut self, value: i64) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_i128(&mut self, value: i128) {
+        self.write_u128(value as u128);
+    }
+
+    fn write_isize(&mut self, value: isize) {
+        self.write_u64(value as u64);
+    }
+}
+
+/// Finds the representative of a vertex's set in a union find forest.
+fn find_root(roots: &mut HashMap<VertexId, VertexId>, vertex: VertexId) -> VertexId {
+    let mut root = vertex;
+
+    while let Some(&parent) = roots.get(&root) {
+        root = parent;
+    }
+
+    // point everything along the path directly at the root
+    let mut current = vertex;
+
+    while let Some(&parent) = roots.get(&current) {
+        if parent == root {
+            break;
+        }
+
+        roots.insert(current, root);
+        current = parent;
+    }
+
+    root
+}
+
+/// `|r|² I - r rᵀ`, the inertia tensor of a unit point mass at `r`.
+fn point_inertia(r: Position) -> Matrix {
+    Matrix::from_diagonal(Position::splat(r.length_squared()))
+        - Matrix::from_cols(r * r.x, r * r.y, r * r.z)
+}
+
+/// The point on the segment from `a` to `b` that is closest to `point`.
+pub(crate) fn closest_point_on_segment(point: Position, a: Position, b: Position) -> Position {
+    let segment = b - a;
+    let length_squared = segment.length_squared();
+
+    let t = if length_squared > 0. {
+        ((point - a).dot(segment) / length_squared).clamp(0., 1.)
+    } else {
+        0.
+    };
+
+    a + segment * t
+}
+
+/// A vertex in the queue of [Graph::shortest_path], ordered so the cheapest is popped first.
+struct PathCandidate {
+    cost: Scalar,
+    vertex: VertexId,
+}
+
+impl PartialEq for PathCandidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PathCandidate {}
+
+impl PartialOrd for PathCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PathCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.vertex.cmp(&self.vertex))
+    }
+}
+
+impl<V> Vertex<V> {
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Panics if the position has a NaN or infinite component,
+    /// which would break every geometric query of the graph.
+    pub fn set_position(&mut self, position: Position) {
+        if !position.is_finite() {
+            panic!("Tried to move a vertex to a non finite position.");
+        }
+
+        self.position = position;
+    }
+
+    pub fn data(&self) -> &V {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut V {
+        &mut self.data
+    }
+
+    pub fn connections(&self) -> &[BeamEnd] {
+        self.connections.as_slice()
+    }
+}
+
+/// Read only access to a frame, implemented by both the server and client `ShipFrame`s
+/// so that code which only reads frames can be written once for either.
+///
+/// The server's [crate::server::ShipFrame] is the authoritative copy that records edits,
+/// the client's [crate::client::ShipFrame] is a copy kept in sync by applying those edits.
+pub trait FrameView<B, V = ()> {
+    fn graph(&self) -> &Graph<B, V>;
+
+    fn get_vertex<'a>(&'a self, id: VertexId) -> Option<&'a Vertex<V>>
+    where
+        B: 'a,
+        V: 'a,
+    {
+        self.graph().get_vertex(id)
+    }
+
+    fn iter_vertices<'a>(&'a self) -> Box<dyn Iterator<Item = (VertexId, &'a Vertex<V>)> + 'a>
+    where
+        B: 'a,
+        V: 'a,
+    {
+        Box::new(self.graph().iter_vertices())
+    }
+
+    fn iter_beams<'a>(&'a self) -> Box<dyn Iterator<Item = (BeamId, &'a B)> + 'a>
+    where
+        B: 'a,
+        V: 'a,
+    {
+        Box::new(self.graph().iter_beams())
+    }
+
+    /// See [Graph::bounds].
+    fn bounds<'a>(&'a self) -> Option<(Position, Position)>
+    where
+        B: 'a,
+        V: 'a,
+    {
+        self.graph().bounds()
+    }
+}
+
+impl<B, V> FrameView<B, V> for Graph<B, V> {
+    fn graph(&self) -> &Graph<B, V> {
+        self
+    }
+}
+
+/// Graphs are equal if they have the same vertex positions, vertex data and beam data,
+/// regardless of the order they were inserted in.
+impl<B: PartialEq, V: PartialEq> PartialEq for Graph<B, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.vertices.len() == other.vertices.len()
+            && self.beams.len() == other.beams.len()
+            && self.vertices.iter().all(|(id, vertex)| {
+                other.vertices.get(id).is_some_and(|other_vertex| {
+                    other_vertex.position == vertex.position && other_vertex.data == vertex.data
+                })
+            })
+            && self.beams.iter().all(|(id, beam_data)| {
+                other
+                    .beams
+                    .get(id)
+                    .is_some_and(|other_data| other_data == beam_data)
+            })
+    }
+}
+
+impl<B, V> std::fmt::Debug for Graph<B, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Graph {{ vertices: {}, beams: {} }}",
+            self.vertices.len(),
+            self.beams.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::FrameIdWorld;
+
+    #[test]
+    fn shortest_path_takes_the_cheaper_route() {
+        let mut id_world = FrameIdWorld::default();
+        let [a, b, c, d, e, f] = [(); 6].map(|_| id_world.next());
+
+        // a diamond where the route through b is cheaper and shorter than the route through c
+        let mut graph = Graph::<Scalar>::default();
+        graph
+            .add_beam(a, Some(Position::ZERO), b, Some(Position::X), 1.)
+            .unwrap();
+        graph
+            .add_beam(a, None, c, Some(Position::Y * 3.), 3.)
+            .unwrap();
+        graph.add_beam(b, None, d, Some(Position::ONE), 1.).unwrap();
+        graph.add_beam(c, None, d, None, 3.).unwrap();
+
+        // a separate component that can't be reached from the diamond
+        graph
+            .add_beam(e, Some(Position::Z), f, Some(Position::NEG_Z), 1.)
+            .unwrap();
+
+        let cost = |_, beam_data: &Scalar| *beam_data;
+
+        assert_eq!(graph.shortest_path(a, d, cost), Some(vec![a, b, d]));
+        assert_eq!(
+            graph.shortest_path(a, d, |id, _| graph.beam_length(id).unwrap()),
+            Some(vec![a, b, d])
+        );
+        assert_eq!(graph.shortest_path(d, a, cost), Some(vec![d, b, a]));
+        assert_eq!(graph.shortest_path(a, e, cost), None);
+    }
+
+    #[test]
+    fn removals_keep_iteration_order() {
+        let mut id_world = FrameIdWorld::default();
+        let [a, b, c, d, e, f] = [(); 6].map(|_| id_world.next());
+        let beam = BeamId::from_vertices;
+
+        // a ring inserted in order a, b, c, d, e, f
+        let mut graph = Graph::<u32>::default();
+        graph
+            .add_beam(a, Some(Position::ZERO), b, Some(Position::X), 0)
+            .unwrap();
+        graph.add_beam(b, None, c, Some(Position::Y), 1).unwrap();
+        graph.add_beam(c, None, d, Some(Position::Z), 2).unwrap();
+        graph.add_beam(d, None, e, Some(Position::ONE), 3).unwrap();
+        graph
+            .add_beam(e, None, f, Some(Position::NEG_X), 4)
+            .unwrap();
+        graph.add_beam(f, None, a, None, 5).unwrap();
+
+        let vertex_order = |graph: &Graph<u32>| -> Vec<VertexId> {
+            graph.iter_vertices().map(|(id, _)| id).collect()
+        };
+        let beam_order =
+            |graph: &Graph<u32>| -> Vec<BeamId> { graph.iter_beams().map(|(id, _)| id).collect() };
+
+        assert_eq!(graph.remove_beam(beam(c, d)), 2);
+        assert_eq!(vertex_order(&graph), vec![a, b, c, d, e, f]);
+        assert_eq!(
+            beam_order(&graph),
+            vec![beam(a, b), beam(b, c), beam(d, e), beam(e, f), beam(a, f)]
+        );
+
+        // removing b leaves c without connections, so it goes too
+        graph.remove_vertex(b);
+        assert_eq!(vertex_order(&graph), vec![a, d, e, f]);
+        assert_eq!(beam_order(&graph), vec![beam(d, e), beam(e, f), beam(a, f)]);
+
+        // re-inserted vertices and beams go at the end
+        graph.add_beam(c, Some(Position::Y), d, None, 6).unwrap();
+        assert_eq!(vertex_order(&graph), vec![a, d, e, f, c]);
+        assert_eq!(
+            beam_order(&graph),
+            vec![beam(d, e), beam(e, f), beam(a, f), beam(c, d)]
+        );
+    }
+
+    #[test]
+    fn weld_keeps_the_smallest_id() {
+        let mut id_world = FrameIdWorld::default();
+        let [a, b, c, d] = [(); 4].map(|_| id_world.next());
+
+        // the newer vertex is inserted first, so insertion order disagrees with id order
+        let mut graph = Graph::<u32>::default();
+        graph
+            .add_beam(b, Some(Position::X), c, Some(Position::Y), 1)
+            .unwrap();
+        graph
+            .add_beam(a, Some(Position::X * 1.001), d, Some(Position::Z), 2)
+            .unwrap();
+
+        assert_eq!(graph.weld_coincident(0.01), 1);
+        assert!(graph.contains_vertex(a) && !graph.contains_vertex(b));
+        assert_eq!(graph.get_vertex(a).unwrap().position(), Position::X * 1.001);
+        assert_eq!(graph.get_vertex(a).unwrap().connections().len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tried to weld with an epsilon that isn't finite and positive.")]
+    fn weld_rejects_non_finite_epsilon() {
+        Graph::<u32>::default().weld_coincident(Scalar::NAN);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tried to weld with an epsilon that isn't finite and positive.")]
+    fn weld_rejects_zero_epsilon() {
+        Graph::<u32>::default().weld_coincident(0.);
+    }
+
+    #[test]
+    fn beams_in_radius_ignores_invalid_radii() {
+        let mut id_world = FrameIdWorld::default();
+        let [a, b] = [(); 2].map(|_| id_world.next());
+
+        let mut graph = Graph::<u32>::default();
+        graph
+            .add_beam(a, Some(Position::NEG_X), b, Some(Position::X), 0)
+            .unwrap();
+
+        // both ends are outside the sphere but the middle of the beam passes through it
+        let center = Position::Y * 0.5;
+        assert_eq!(
+            graph.beams_in_radius(center, 1.),
+            vec![BeamId::from_vertices(a, b)]
+        );
+        assert!(graph.beams_in_radius(center, 0.25).is_empty());
+
+        assert!(graph.beams_in_radius(center, -1.).is_empty());
+        assert!(graph.beams_in_radius(center, Scalar::NAN).is_empty());
+        assert!(graph.beams_in_radius(center, Scalar::INFINITY).is_empty());
     }
 }
@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::{
+    messages::{SerializedGraph, ValidationError},
+    server::ShipFrame,
+    BeamId, VertexId,
+};
+
+/// Vertex ids are made up of an index in the low 32 bits
+/// and a generation in the high 32 bits that is incremented each time the index is recycled.
+const GENERATION_SHIFT: u32 = 32;
+
+/// Allocates [VertexId]s, this is the [IdSource] used by most servers.
+///
+/// Ids given back with [FrameIdAllocator::free] are handed out again by [FrameIdAllocator::next]
+/// with their generation incremented, so a recycled id never equals a stale copy of itself.
+///
+/// The generation is in the high bits, so a recycled id is greater than every id that was never
+/// recycled, including ones allocated after it. Because of this the "down" end of a beam is
+/// always the smaller id, but is only guaranteed to be the older vertex if ids aren't recycled.
+#[derive(Resource, Default)]
+pub struct FrameIdAllocator {
+    next_id: u64,
+    free: VecDeque<VertexId>,
+    /// The generation of each index that is in use, `None` while it's waiting to be reused.
+    generations: Vec<Option<u32>>,
+}
+
+impl FrameIdAllocator {
+    /// Allocates an id, reusing the longest freed id if there is one.
+    ///
+    /// Panics if all 2^32 indices have been allocated.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> VertexId {
+        let id = self.peek_next();
+
+        if self.free.pop_front().is_none() {
+            self.next_id += 1;
+            self.generations.push(Some(0));
+        } else {
+            self.generations[index(id)] = Some(generation(id));
+        }
+
+        id
+    }
+
+    /// Returns the id that [FrameIdAllocator::next] would allocate, without allocating it.
+    ///
+    /// Panics if all 2^32 indices have been allocated.
+    pub fn peek_next(&self) -> VertexId {
+        if let Some(&id) = self.free.front() {
+            return id;
+        }
+
+        if self.next_id >> GENERATION_SHIFT != 0 {
+            panic!("Ran out of vertex ids.");
+        }
+
+        VertexId(self.next_id)
+    }
+
+    /// Gives an id back to be reused by [FrameIdAllocator::next].
+    ///
+    /// The id must no longer be in use by any frame.
+    /// Ids that weren't handed out by this allocator, have already been freed, or are stale copies
+    /// of a recycled id are ignored. Ids whose generation has run out are retired instead.
+    pub fn free(&mut self, id: VertexId) {
+        let Some(slot) = self.generations.get_mut(index(id)) else {
+            return;
+        };
+
+        if *slot != Some(generation(id)) {
+            return;
+        }
+
+        *slot = None;
+
+        if generation(id) == u32::MAX {
+            return;
+        }
+
+        self.free
+            .push_back(VertexId(id.0 + (1 << GENERATION_SHIFT)));
+    }
+}
+
+fn index(id: VertexId) -> usize {
+    (id.0 & u32::MAX as u64) as usize
+}
+
+fn generation(id: VertexId) -> u32 {
+    (id.0 >> GENERATION_SHIFT) as u32
+}
+
+/// Something that hands out [VertexId]s for new vertices,
+/// which frames and the functions editing them are generic over.
+///
+/// The default is [FrameIdAllocator], a custom source can eg. hand out ids from a range reserved
+/// for one node of a distributed server. Every id must be unique among the sources whose frames
+/// can be merged or joined, see [crate::server::ShipFrame::merge].
+pub trait IdSource {
+    /// Hands out an id that hasn't been handed out before.
+    fn next(&mut self) -> VertexId;
+
+    /// Maps a graph into this source's scope,
+    /// ensuring that ids within the graph stay consistent,
+    /// but don't reference any existing ids.
+    fn map_graph<B, V>(&mut self, graph: SerializedGraph<B, V>) -> SerializedGraph<B, V> {
+        self.map_graph_with_remap(graph).0
+    }
+
+    /// Same as [IdSource::map_graph] but also returns the map from old ids to new ids.
+    ///
+    /// New ids are allocated in ascending order of the old ids,
+    /// so mapping the same graph from the same source state always gives the same result.
+    fn map_graph_with_remap<B, V>(
+        &mut self,
+        mut graph: SerializedGraph<B, V>,
+    ) -> (SerializedGraph<B, V>, BTreeMap<VertexId, VertexId>) {
+        let mut map = BTreeMap::new();
+
+        for &(id, ..) in graph.vertices.iter() {
+            map.insert(id, id);
+        }
+
+        for (id, _) in graph.beams.iter() {
+            let (id_a, id_b) = id.vertices();
+            map.insert(id_a, id_a);
+            map.insert(id_b, id_b);
+        }
+
+        for new_id in map.values_mut() {
+            *new_id = IdSource::next(self);
+        }
+
+        for (id, ..) in graph.vertices.iter_mut() {
+            *id = map[id];
+        }
+
+        for (id, _) in graph.beams.iter_mut() {
+            let (id_a, id_b) = id.vertices();
+            *id = BeamId::from_vertices(map[&id_a], map[&id_b]);
+        }
+
+        (graph, map)
+    }
+
+    /// Maps a frame graph into this source's scope,
+    /// ensuring that ids within the graph stay consistent,
+    /// but don't reference any existing ids.
+    ///
+    /// Panics if the graph is invalid, use [IdSource::try_map_frame] for untrusted input.
+    fn map_frame<B, V>(&mut self, graph: SerializedGraph<B, V>) -> ShipFrame<B, V> {
+        ShipFrame::from_graph(self.map_graph(graph).into())
+    }
+
+    /// Same as [IdSource::map_frame] but returns an error instead of panicking if the graph is invalid,
+    /// in which case no ids are allocated.
+    fn try_map_frame<B, V>(
+        &mut self,
+        graph: SerializedGraph<B, V>,
+    ) -> Result<ShipFrame<B, V>, ValidationError> {
+        graph.validate()?;
+        Ok(self.map_frame(graph))
+    }
+
+    /// Same as [IdSource::map_frame] but also returns the map from old ids to new ids,
+    /// eg. to find where a specific vertex of a prefab ended up.
+    ///
+    /// Panics if the graph is invalid.
+    fn map_frame_with_remap<B, V>(
+        &mut self,
+        graph: SerializedGraph<B, V>,
+    ) -> (ShipFrame<B, V>, BTreeMap<VertexId, VertexId>) {
+        let (graph, map) = self.map_graph_with_remap(graph);
+        (ShipFrame::from_graph(graph.into()), map)
+    }
+}
+
+impl IdSource for FrameIdAllocator {
+    fn next(&mut self) -> VertexId {
+        FrameIdAllocator::next(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_free_is_ignored() {
+        let mut allocator = FrameIdAllocator::default();
+        let id = allocator.next();
+        allocator.next();
+
+        allocator.free(id);
+        allocator.free(id);
+
+        let recycled = allocator.next();
+        assert!(recycled > id);
+        assert_ne!(allocator.next(), recycled);
+
+        // a stale copy of the recycled id doesn't free it
+        allocator.free(id);
+        assert_ne!(allocator.next(), recycled);
+    }
+
+    #[test]
+    #[should_panic(expected = "Ran out of vertex ids.")]
+    fn fresh_ids_run_out() {
+        let mut allocator = FrameIdAllocator {
+            next_id: u32::MAX as u64 + 1,
+            ..Default::default()
+        };
+
+        allocator.next();
+    }
+}
@@ -1,10 +1,41 @@
 use serde::{Deserialize, Serialize};
 
 pub mod client;
+#[cfg(feature = "bevy_gizmos")]
+pub mod debug;
+mod error;
 pub mod graph;
+pub mod ids;
 pub mod messages;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "physics")]
+pub mod physics;
+pub mod prefab;
 pub mod server;
 
+pub use error::Error;
+
+/// The type of vertex positions, [DVec3](bevy::math::DVec3) with the `f64` feature.
+///
+/// The feature changes the serialized format, so the server and clients need to agree on it.
+#[cfg(not(feature = "f64"))]
+pub type Position = bevy::math::Vec3;
+#[cfg(feature = "f64")]
+pub type Position = bevy::math::DVec3;
+
+/// The type of lengths and distances between [Position]s.
+#[cfg(not(feature = "f64"))]
+pub type Scalar = f32;
+#[cfg(feature = "f64")]
+pub type Scalar = f64;
+
+/// The type of 3x3 matrices of [Scalar]s, [DMat3](bevy::math::DMat3) with the `f64` feature.
+#[cfg(not(feature = "f64"))]
+pub type Matrix = bevy::math::Mat3;
+#[cfg(feature = "f64")]
+pub type Matrix = bevy::math::DMat3;
+
 #[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BeamDirection {
     Down,
@@ -15,6 +46,8 @@ pub enum BeamDirection {
 ///
 /// Beams are undirected but the `Down`
 /// direction always points to the older vertex id.
+///
+/// See [ids::FrameIdAllocator] for how this is affected by recycled ids.
 impl BeamDirection {
     pub fn opposite(self) -> Self {
         match self {
@@ -24,21 +57,56 @@ impl BeamDirection {
     }
 }
 
-/// A vertex id unique to a [FrameIdAllocator].
+/// A vertex id unique to an [IdSource](ids::IdSource).
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct VertexId(u64);
 
+impl VertexId {
+    /// Creates an id from it's raw bits, eg. for implementing a custom [IdSource](ids::IdSource).
+    pub const fn from_bits(bits: u64) -> Self {
+        VertexId(bits)
+    }
+
+    /// The raw bits of the id.
+    pub const fn to_bits(self) -> u64 {
+        self.0
+    }
+}
+
 /// A beam id made up of two [VertexId]s.
 ///
 /// The older vertex id is the "down" vertex.
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+/// Deserialized ids have their vertices swapped if needed so this always holds,
+/// and ids with the same vertex at both ends fail to deserialize.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedBeamId")]
 pub struct BeamId {
     down_id: u64,
     up_id: u64,
 }
 
+/// A deserialized [BeamId] whose vertices might not be in order.
+#[derive(Deserialize)]
+#[serde(rename = "BeamId")]
+struct UncheckedBeamId {
+    down_id: u64,
+    up_id: u64,
+}
+
+impl TryFrom<UncheckedBeamId> for BeamId {
+    type Error = &'static str;
+
+    fn try_from(id: UncheckedBeamId) -> Result<Self, Self::Error> {
+        BeamId::try_from_vertices(VertexId(id.down_id), VertexId(id.up_id))
+            .ok_or("beam has the same vertex at both ends")
+    }
+}
+
 impl BeamId {
     /// Creates the id of a beam that could exist between two vertices.
+    ///
+    /// If `a` and `b` are the same the id is of a degenerate beam that can't be inserted,
+    /// see [BeamId::try_from_vertices].
     pub fn from_vertices(a: VertexId, b: VertexId) -> Self {
         if a < b {
             BeamId {
@@ -53,6 +121,11 @@ impl BeamId {
         }
     }
 
+    /// Same as [BeamId::from_vertices] but `None` if `a` and `b` are the same vertex.
+    pub fn try_from_vertices(a: VertexId, b: VertexId) -> Option<Self> {
+        (a != b).then(|| BeamId::from_vertices(a, b))
+    }
+
     pub fn vertices(self) -> (VertexId, VertexId) {
         (VertexId(self.down_id), VertexId(self.up_id))
     }
@@ -73,16 +146,52 @@ impl BeamId {
     }
 }
 
+/// The end of a beam that a vertex is connected to, see [graph::Vertex::connections].
 pub struct BeamEnd {
     pub beam_id: BeamId,
     pub beam_end: BeamDirection,
 }
 
 impl BeamEnd {
+    /// The vertex at this end of the beam.
+    pub fn near_vertex(&self) -> VertexId {
+        self.beam_id.vertex(self.beam_end)
+    }
+
+    /// The vertex at the other end of the beam.
+    pub fn far_vertex(&self) -> VertexId {
+        self.beam_id.vertex(self.beam_end.opposite())
+    }
+
+    #[deprecated = "Use `far_vertex`, which returns the same vertex."]
     pub fn opposite(&self) -> VertexId {
-        match self.beam_end {
-            BeamDirection::Down => self.beam_id.up_vertex(),
-            BeamDirection::Up => self.beam_id.down_vertex(),
-        }
+        self.far_vertex()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::{value::MapDeserializer, Deserialize};
+
+    use super::*;
+
+    fn deserialize_beam_id(down_id: u64, up_id: u64) -> Result<BeamId, serde::de::value::Error> {
+        BeamId::deserialize(MapDeserializer::new(
+            [("down_id", down_id), ("up_id", up_id)].into_iter(),
+        ))
+    }
+
+    #[test]
+    fn deserialized_beam_ids_are_normalized() {
+        let id = deserialize_beam_id(5, 2).unwrap();
+
+        assert_eq!(id.down_vertex(), VertexId(2));
+        assert_eq!(id.up_vertex(), VertexId(5));
+        assert_eq!(id, BeamId::from_vertices(VertexId(2), VertexId(5)));
+    }
+
+    #[test]
+    fn deserialized_self_loops_are_rejected() {
+        assert!(deserialize_beam_id(3, 3).is_err());
     }
 }
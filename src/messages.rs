@@ -1,15 +1,21 @@
-use bevy::math::Vec3;
+use std::{collections::BTreeMap, fmt};
+
+use bevy::utils::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
-use crate::{graph::*, BeamDirection, BeamEnd, BeamId, VertexId};
+use crate::{
+    graph::*,
+    ids::{FrameIdAllocator, IdSource},
+    BeamDirection, BeamEnd, BeamId, Position, Scalar, VertexId,
+};
 
 #[derive(Serialize, Deserialize, Clone)]
-pub struct SerializedGraph<B> {
-    pub(crate) vertices: Vec<(VertexId, Vec3)>,
+pub struct SerializedGraph<B, V = ()> {
+    pub(crate) vertices: Vec<(VertexId, Position, V)>,
     pub(crate) beams: Vec<(BeamId, B)>,
 }
 
-impl<B> Default for SerializedGraph<B> {
+impl<B, V> Default for SerializedGraph<B, V> {
     fn default() -> Self {
         SerializedGraph {
             vertices: Vec::new(),
@@ -18,77 +24,1358 @@ impl<B> Default for SerializedGraph<B> {
     }
 }
 
-impl<B> From<&Graph<B>> for SerializedGraph<B>
+/// The problems that make a [SerializedGraph] invalid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    DuplicateVertex(VertexId),
+    DuplicateBeam(BeamId),
+    /// A beam connects a vertex to itself.
+    SelfLoop(BeamId),
+    /// A beam references a vertex that isn't in the graph.
+    MissingVertex(VertexId),
+    /// A vertex's position has a NaN or infinite component.
+    NonFinitePosition(VertexId),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::DuplicateVertex(id) => write!(f, "vertex {:?} is duplicated", id),
+            ValidationError::DuplicateBeam(id) => write!(f, "beam {:?} is duplicated", id),
+            ValidationError::SelfLoop(id) => write!(f, "beam {:?} connects a vertex to itself", id),
+            ValidationError::MissingVertex(id) => {
+                write!(f, "a beam references missing vertex {:?}", id)
+            }
+            ValidationError::NonFinitePosition(id) => {
+                write!(f, "vertex {:?} has a non finite position", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// The reasons a [SerializedGraph] can't be added to a graph with [Graph::extend_from].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtendError {
+    /// A vertex id is in both graphs.
+    VertexCollision(VertexId),
+    /// A beam id is in both graphs.
+    BeamCollision(BeamId),
+    /// The added graph is invalid on it's own.
+    Invalid(ValidationError),
+}
+
+impl fmt::Display for ExtendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtendError::VertexCollision(id) => write!(f, "vertex {:?} is in both graphs", id),
+            ExtendError::BeamCollision(id) => write!(f, "beam {:?} is in both graphs", id),
+            ExtendError::Invalid(_) => write!(f, "added graph is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for ExtendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExtendError::Invalid(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl<B, V> SerializedGraph<B, V> {
+    /// Checks that the graph can be built, returning the first problem found.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut vertices = HashSet::new();
+
+        for &(id, position, _) in self.vertices.iter() {
+            if !vertices.insert(id) {
+                return Err(ValidationError::DuplicateVertex(id));
+            }
+
+            if !position.is_finite() {
+                return Err(ValidationError::NonFinitePosition(id));
+            }
+        }
+
+        let mut beams = HashSet::new();
+
+        for &(id, _) in self.beams.iter() {
+            if !beams.insert(id) {
+                return Err(ValidationError::DuplicateBeam(id));
+            }
+
+            if id.down_vertex() == id.up_vertex() {
+                return Err(ValidationError::SelfLoop(id));
+            }
+
+            for vertex in [id.down_vertex(), id.up_vertex()] {
+                if !vertices.contains(&vertex) {
+                    return Err(ValidationError::MissingVertex(vertex));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates a copy with vertices and beams sorted by id,
+    /// so that equal graphs are encoded the same regardless of where they came from.
+    pub fn canonical(&self) -> SerializedGraph<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let mut canonical = self.clone();
+        canonical.vertices.sort_unstable_by_key(|&(id, ..)| id);
+        canonical.beams.sort_unstable_by_key(|&(id, _)| id);
+        canonical
+    }
+
+    /// Builds the graph if it is valid, use this for untrusted input.
+    ///
+    /// This never panics, whatever the graph contains.
+    pub fn try_into_graph(self) -> Result<Graph<B, V>, ValidationError> {
+        let mut graph = Graph::with_capacity(self.vertices.len(), self.beams.len());
+
+        for (id, position, data) in self.vertices {
+            graph.insert_serialized_vertex(id, position, data)?;
+        }
+
+        for (id, beam_data) in self.beams {
+            graph.insert_serialized_beam(id, beam_data)?;
+        }
+
+        Ok(graph)
+    }
+
+    /// Renumbers the vertices to the dense range `0..n`, keeping the order of their ids,
+    /// returning the map from old ids to new ids.
+    ///
+    /// The new ids are local to the graph like a [Prefab](crate::prefab::Prefab)'s,
+    /// see [ShipFrame::compact_ids](crate::server::ShipFrame::compact_ids) for a live frame.
+    pub fn compact_ids(&mut self) -> BTreeMap<VertexId, VertexId> {
+        let (graph, map) = FrameIdAllocator::default().map_graph_with_remap(std::mem::take(self));
+        *self = graph;
+        map
+    }
+
+    /// Copies the graph into a [ColumnarGraph].
+    pub fn to_columnar(&self) -> ColumnarGraph<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        ColumnarGraph {
+            vertex_ids: self.vertices.iter().map(|&(id, ..)| id).collect(),
+            positions: self
+                .vertices
+                .iter()
+                .map(|&(_, position, _)| position)
+                .collect(),
+            vertex_data: self
+                .vertices
+                .iter()
+                .map(|(.., vertex_data)| vertex_data.clone())
+                .collect(),
+            beam_ids: self.beams.iter().map(|(id, _)| *id).collect(),
+            beam_data: self
+                .beams
+                .iter()
+                .map(|(_, beam_data)| beam_data.clone())
+                .collect(),
+        }
+    }
+
+    /// Rebuilds a graph from a [ColumnarGraph], failing if it's columns have different lengths.
+    pub fn from_columnar(columnar: ColumnarGraph<B, V>) -> Result<Self, FormatError> {
+        if columnar.vertex_ids.len() != columnar.positions.len()
+            || columnar.vertex_ids.len() != columnar.vertex_data.len()
+            || columnar.beam_ids.len() != columnar.beam_data.len()
+        {
+            return Err(FormatError::ColumnLengthMismatch);
+        }
+
+        Ok(SerializedGraph {
+            vertices: columnar
+                .vertex_ids
+                .into_iter()
+                .zip(columnar.positions)
+                .zip(columnar.vertex_data)
+                .map(|((id, position), vertex_data)| (id, position, vertex_data))
+                .collect(),
+            beams: columnar
+                .beam_ids
+                .into_iter()
+                .zip(columnar.beam_data)
+                .collect(),
+        })
+    }
+}
+
+/// A [SerializedGraph] with each field of it's vertices and beams stored in a separate list.
+///
+/// Repetitive beam data ends up next to each other when encoded instead of between positions,
+/// eg. for a compressor to pick up on. Uncompressed it's the same size apart from the extra list lengths.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ColumnarGraph<B, V = ()> {
+    vertex_ids: Vec<VertexId>,
+    positions: Vec<Position>,
+    vertex_data: Vec<V>,
+    beam_ids: Vec<BeamId>,
+    beam_data: Vec<B>,
+}
+
+impl<B, V> ColumnarGraph<B, V> {
+    pub fn vertex_ids(&self) -> &[VertexId] {
+        &self.vertex_ids
+    }
+
+    /// The position of each vertex in [ColumnarGraph::vertex_ids].
+    pub fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+
+    /// The data of each vertex in [ColumnarGraph::vertex_ids].
+    pub fn vertex_data(&self) -> &[V] {
+        &self.vertex_data
+    }
+
+    pub fn beam_ids(&self) -> &[BeamId] {
+        &self.beam_ids
+    }
+
+    /// The data of each beam in [ColumnarGraph::beam_ids].
+    pub fn beam_data(&self) -> &[B] {
+        &self.beam_data
+    }
+}
+
+/// The version of the [SerializedGraph] layout,
+/// incremented whenever a change would make old data decode incorrectly.
+pub const GRAPH_FORMAT_VERSION: u32 = 1;
+
+/// The reasons encoded data can't be decoded into a [SerializedGraph].
+#[derive(Debug)]
+pub enum FormatError {
+    /// The data was encoded with a different [GRAPH_FORMAT_VERSION].
+    UnsupportedVersion(u32),
+    /// The columns of a [ColumnarGraph] have different lengths.
+    ColumnLengthMismatch,
+    /// The data decoded but doesn't describe a valid graph.
+    Invalid(ValidationError),
+    #[cfg(feature = "bincode")]
+    Bincode(bincode::Error),
+    #[cfg(feature = "ron")]
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::UnsupportedVersion(version) => {
+                write!(f, "unsupported graph format version {}", version)
+            }
+            FormatError::ColumnLengthMismatch => write!(f, "graph columns have different lengths"),
+            FormatError::Invalid(_) => write!(f, "decoded graph is invalid"),
+            #[cfg(feature = "bincode")]
+            FormatError::Bincode(error) => write!(f, "bincode error: {}", error),
+            #[cfg(feature = "ron")]
+            FormatError::Ron(error) => write!(f, "ron error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::UnsupportedVersion(_) | FormatError::ColumnLengthMismatch => None,
+            FormatError::Invalid(error) => Some(error),
+            #[cfg(feature = "bincode")]
+            FormatError::Bincode(error) => Some(error),
+            #[cfg(feature = "ron")]
+            FormatError::Ron(error) => Some(error),
+        }
+    }
+}
+
+/// Compact binary encoding using bincode.
+///
+/// The bytes start with the [GRAPH_FORMAT_VERSION] as a little endian `u32`,
+/// data from other versions is rejected instead of being misinterpreted.
+#[cfg(feature = "bincode")]
+impl<B, V> SerializedGraph<B, V> {
+    pub fn to_bytes(&self) -> Vec<u8>
+    where
+        B: Serialize,
+        V: Serialize,
+    {
+        bincode::serialize(&(GRAPH_FORMAT_VERSION, self))
+            .expect("Serializing to a `Vec` shouldn't fail.")
+    }
+
+    /// The graph is validated, so it's safe to build a graph from even if the bytes came from a peer.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FormatError>
+    where
+        B: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+    {
+        let version: u32 = bincode::deserialize(bytes).map_err(FormatError::Bincode)?;
+
+        if version != GRAPH_FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        let (_, graph): (u32, Self) = bincode::deserialize(bytes).map_err(FormatError::Bincode)?;
+        graph.validate().map_err(FormatError::Invalid)?;
+
+        Ok(graph)
+    }
+}
+
+/// Human readable encoding using RON, eg. for hand written blueprints.
+///
+/// The text is a tuple of the [GRAPH_FORMAT_VERSION] and the graph,
+/// with every vertex and beam on it's own line.
+#[cfg(feature = "ron")]
+impl<B, V> SerializedGraph<B, V> {
+    pub fn to_ron(&self) -> String
+    where
+        B: Serialize,
+        V: Serialize,
+    {
+        let config = ron::ser::PrettyConfig::new().depth_limit(2);
+
+        ron::ser::to_string_pretty(&(GRAPH_FORMAT_VERSION, self), config)
+            .expect("Serializing to a `String` shouldn't fail.")
+    }
+
+    /// Like [SerializedGraph::from_bytes] the graph is validated.
+    pub fn from_ron(text: &str) -> Result<Self, FormatError>
+    where
+        B: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+    {
+        let (version, graph): (u32, Self) = ron::from_str(text).map_err(FormatError::Ron)?;
+
+        if version != GRAPH_FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        graph.validate().map_err(FormatError::Invalid)?;
+
+        Ok(graph)
+    }
+}
+
+/// Vertices and beams are sorted by id.
+impl<B, V> From<&Graph<B, V>> for SerializedGraph<B, V>
 where
     B: Clone,
+    V: Clone,
 {
-    fn from(graph: &Graph<B>) -> Self {
+    fn from(graph: &Graph<B, V>) -> Self {
         let mut serialized = SerializedGraph::default();
 
         for (&id, vertex) in graph.vertices.iter() {
-            serialized.vertices.push((id, vertex.position()));
+            serialized
+                .vertices
+                .push((id, vertex.position(), vertex.data.clone()));
         }
 
         for (&id, beam_data) in graph.beams.iter() {
             serialized.beams.push((id, beam_data.clone()));
         }
 
+        // the graph's order depends on it's history of insertions,
+        // sorting makes equal graphs serialize the same
+        serialized.vertices.sort_unstable_by_key(|&(id, ..)| id);
+        serialized.beams.sort_unstable_by_key(|&(id, _)| id);
+
         serialized
     }
 }
 
-impl<B> From<SerializedGraph<B>> for Graph<B> {
-    fn from(serialized: SerializedGraph<B>) -> Self {
-        let mut graph = Graph::default();
+/// A [SerializedGraph] that borrows the vertex and beam data of a graph instead of cloning it.
+///
+/// It serializes exactly like the equivalent [SerializedGraph], so it can be deserialized as one.
+#[derive(Serialize)]
+#[serde(rename = "SerializedGraph")]
+pub struct SerializedGraphRef<'a, B, V = ()> {
+    vertices: Vec<(VertexId, Position, &'a V)>,
+    beams: Vec<(BeamId, &'a B)>,
+}
 
-        for (id, position) in serialized.vertices {
-            graph.vertices.insert(
-                id,
-                Vertex {
-                    position,
-                    connections: Vec::new(),
-                },
-            );
+/// Vertices and beams are sorted by id, like [SerializedGraph::from].
+impl<'a, B, V> From<&'a Graph<B, V>> for SerializedGraphRef<'a, B, V> {
+    fn from(graph: &'a Graph<B, V>) -> Self {
+        let mut serialized = SerializedGraphRef {
+            vertices: graph
+                .vertices
+                .iter()
+                .map(|(&id, vertex)| (id, vertex.position, &vertex.data))
+                .collect(),
+            beams: graph
+                .beams
+                .iter()
+                .map(|(&id, beam_data)| (id, beam_data))
+                .collect(),
+        };
+
+        serialized.vertices.sort_unstable_by_key(|&(id, ..)| id);
+        serialized.beams.sort_unstable_by_key(|&(id, _)| id);
+
+        serialized
+    }
+}
+
+/// Same as [SerializedGraph::to_bytes].
+#[cfg(feature = "bincode")]
+impl<B: Serialize, V: Serialize> SerializedGraphRef<'_, B, V> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&(GRAPH_FORMAT_VERSION, self))
+            .expect("Serializing to a `Vec` shouldn't fail.")
+    }
+}
+
+/// Panics if the serialized graph is invalid, see [SerializedGraph::try_into_graph].
+impl<B, V> From<SerializedGraph<B, V>> for Graph<B, V> {
+    fn from(serialized: SerializedGraph<B, V>) -> Self {
+        serialized
+            .try_into_graph()
+            .unwrap_or_else(|error| panic!("Invalid serialized graph: {}", error))
+    }
+}
+
+impl<B, V> Graph<B, V> {
+    /// Inserts all vertices and beams of a graph whose ids are already distinct from this one's,
+    /// unlike [IdSource::map_frame] which gives them new ids.
+    ///
+    /// Beams of the added graph can connect to vertices of this graph.
+    ///
+    /// The graph is left unmodified if an error is returned.
+    pub fn extend_from(&mut self, other: SerializedGraph<B, V>) -> Result<(), ExtendError> {
+        let mut vertices = HashSet::new();
+
+        for &(id, position, _) in other.vertices.iter() {
+            if self.vertices.contains_key(&id) {
+                return Err(ExtendError::VertexCollision(id));
+            }
+
+            if !vertices.insert(id) {
+                return Err(ExtendError::Invalid(ValidationError::DuplicateVertex(id)));
+            }
+
+            if !position.is_finite() {
+                return Err(ExtendError::Invalid(ValidationError::NonFinitePosition(id)));
+            }
         }
 
-        for (id, beam_data) in serialized.beams {
-            graph
-                .vertices
-                .get_mut(&id.down_vertex())
-                .expect("Invalid serialized graph structure.")
-                .connections
-                .push(BeamEnd {
-                    beam_id: id,
-                    beam_end: BeamDirection::Down,
-                });
+        let mut beams = HashSet::new();
 
-            graph
-                .vertices
-                .get_mut(&id.up_vertex())
-                .expect("Invalid serialized graph structure.")
-                .connections
-                .push(BeamEnd {
-                    beam_id: id,
-                    beam_end: BeamDirection::Up,
-                });
+        for &(id, _) in other.beams.iter() {
+            if self.beams.contains_key(&id) {
+                return Err(ExtendError::BeamCollision(id));
+            }
+
+            if !beams.insert(id) {
+                return Err(ExtendError::Invalid(ValidationError::DuplicateBeam(id)));
+            }
+
+            if id.down_vertex() == id.up_vertex() {
+                return Err(ExtendError::Invalid(ValidationError::SelfLoop(id)));
+            }
+
+            for vertex in [id.down_vertex(), id.up_vertex()] {
+                if !vertices.contains(&vertex) && !self.vertices.contains_key(&vertex) {
+                    return Err(ExtendError::Invalid(ValidationError::MissingVertex(vertex)));
+                }
+            }
+        }
+
+        self.vertices.reserve(other.vertices.len());
+        self.beams.reserve(other.beams.len());
+
+        for (id, position, data) in other.vertices {
+            self.insert_serialized_vertex(id, position, data)
+                .expect("Vertices were checked before inserting.");
+        }
+
+        for (id, beam_data) in other.beams {
+            self.insert_serialized_beam(id, beam_data)
+                .expect("Beams were checked before inserting.");
+        }
+
+        Ok(())
+    }
+
+    /// Inserts a vertex without any connections, as part of building a graph from untrusted input.
+    fn insert_serialized_vertex(
+        &mut self,
+        id: VertexId,
+        position: Position,
+        data: V,
+    ) -> Result<(), ValidationError> {
+        if self.vertices.contains_key(&id) {
+            return Err(ValidationError::DuplicateVertex(id));
+        }
+
+        if !position.is_finite() {
+            return Err(ValidationError::NonFinitePosition(id));
+        }
+
+        self.vertices.insert(
+            id,
+            Vertex {
+                position,
+                data,
+                connections: Vec::new(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Inserts a beam between existing vertices, as part of building a graph from untrusted input.
+    fn insert_serialized_beam(&mut self, id: BeamId, beam_data: B) -> Result<(), ValidationError> {
+        if self.beams.contains_key(&id) {
+            return Err(ValidationError::DuplicateBeam(id));
+        }
+
+        if id.down_vertex() == id.up_vertex() {
+            return Err(ValidationError::SelfLoop(id));
+        }
+
+        for vertex in [id.down_vertex(), id.up_vertex()] {
+            if !self.vertices.contains_key(&vertex) {
+                return Err(ValidationError::MissingVertex(vertex));
+            }
+        }
 
-            graph.beams.insert(id, beam_data);
+        for (vertex, beam_end) in [
+            (id.down_vertex(), BeamDirection::Down),
+            (id.up_vertex(), BeamDirection::Up),
+        ] {
+            self.vertices[&vertex].connections.push(BeamEnd {
+                beam_id: id,
+                beam_end,
+            });
         }
 
+        self.beams.insert(id, beam_data);
+
+        Ok(())
+    }
+}
+
+/// Streaming versions of [SerializedGraph::to_bytes] and [SerializedGraph::from_bytes]
+/// that use the same encoding, without building a [SerializedGraph] in between.
+///
+/// Reading and writing is done in many small pieces, so the reader or writer should be buffered.
+#[cfg(feature = "bincode")]
+impl<B, V> Graph<B, V> {
+    /// Writes the graph with vertices and beams sorted by id, like [SerializedGraph::from].
+    pub fn to_writer<W: std::io::Write>(&self, mut writer: W) -> Result<(), FormatError>
+    where
+        B: Serialize,
+        V: Serialize,
+    {
+        let mut vertex_ids: Vec<VertexId> = self.vertices.keys().copied().collect();
+        let mut beam_ids: Vec<BeamId> = self.beams.keys().copied().collect();
+        vertex_ids.sort_unstable();
+        beam_ids.sort_unstable();
+
+        bincode::serialize_into(&mut writer, &GRAPH_FORMAT_VERSION)
+            .map_err(FormatError::Bincode)?;
+
+        bincode::serialize_into(&mut writer, &(vertex_ids.len() as u64))
+            .map_err(FormatError::Bincode)?;
+        for id in vertex_ids {
+            let vertex = &self.vertices[&id];
+            bincode::serialize_into(&mut writer, &(id, vertex.position, &vertex.data))
+                .map_err(FormatError::Bincode)?;
+        }
+
+        bincode::serialize_into(&mut writer, &(beam_ids.len() as u64))
+            .map_err(FormatError::Bincode)?;
+        for id in beam_ids {
+            bincode::serialize_into(&mut writer, &(id, &self.beams[&id]))
+                .map_err(FormatError::Bincode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a graph written by [Graph::to_writer] or [SerializedGraph::to_bytes],
+    /// inserting each vertex and beam as soon as it's decoded.
+    ///
+    /// The input is validated as it's read, like [SerializedGraph::try_into_graph].
+    pub fn from_reader<R: std::io::Read>(mut reader: R) -> Result<Self, FormatError>
+    where
+        B: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+    {
+        // counts come from the input, so they are only trusted up to a point for preallocating
+        const MAX_PREALLOCATED: u64 = 1 << 16;
+
+        let version: u32 = bincode::deserialize_from(&mut reader).map_err(FormatError::Bincode)?;
+
+        if version != GRAPH_FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        let vertex_count: u64 =
+            bincode::deserialize_from(&mut reader).map_err(FormatError::Bincode)?;
+        let mut graph = Graph::with_capacity(vertex_count.min(MAX_PREALLOCATED) as usize, 0);
+
+        for _ in 0..vertex_count {
+            let (id, position, data): (VertexId, Position, V) =
+                bincode::deserialize_from(&mut reader).map_err(FormatError::Bincode)?;
+
+            graph
+                .insert_serialized_vertex(id, position, data)
+                .map_err(FormatError::Invalid)?;
+        }
+
+        let beam_count: u64 =
+            bincode::deserialize_from(&mut reader).map_err(FormatError::Bincode)?;
         graph
+            .beams
+            .reserve(beam_count.min(MAX_PREALLOCATED) as usize);
+
+        for _ in 0..beam_count {
+            let (id, beam_data): (BeamId, B) =
+                bincode::deserialize_from(&mut reader).map_err(FormatError::Bincode)?;
+
+            graph
+                .insert_serialized_beam(id, beam_data)
+                .map_err(FormatError::Invalid)?;
+        }
+
+        Ok(graph)
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub enum FrameUpdate<B> {
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum FrameUpdate<B, V = ()> {
+    /// See [Graph::add_beam_with_data].
     AddBeam {
         vertex_a: VertexId,
-        position_a: Option<Vec3>,
+        new_a: Option<(Position, V)>,
         vertex_b: VertexId,
-        position_b: Option<Vec3>,
+        new_b: Option<(Position, V)>,
         beam_data: B,
     },
+    /// `recovered` is filled in by the server so that the removal can be undone
+    /// without the graph it was applied to, it's ignored when applying the update.
     RemoveBeam {
         id: BeamId,
+        recovered: Option<RemovedBeamData<B, V>>,
+    },
+    MoveVertex {
+        id: VertexId,
+        position: Position,
+    },
+    UpdateBeamData {
+        id: BeamId,
+        beam_data: B,
+    },
+    UpdateVertexData {
+        id: VertexId,
+        vertex_data: V,
     },
 }
+
+/// Everything a [FrameUpdate::RemoveBeam] removes from a graph.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct RemovedBeamData<B, V = ()> {
+    pub beam_data: B,
+    /// Vertices removed along with the beam, as it was their last connection.
+    pub removed_vertices: Vec<(VertexId, Position, V)>,
+}
+
+impl<B, V> RemovedBeamData<B, V> {
+    /// Creates the update that adds the removed beam and vertices back.
+    pub fn restore(self, id: BeamId) -> FrameUpdate<B, V> {
+        let mut new_a = None;
+        let mut new_b = None;
+
+        for (vertex, position, data) in self.removed_vertices {
+            if vertex == id.down_vertex() {
+                new_a = Some((position, data));
+            } else if vertex == id.up_vertex() {
+                new_b = Some((position, data));
+            }
+        }
+
+        FrameUpdate::AddBeam {
+            vertex_a: id.down_vertex(),
+            new_a,
+            vertex_b: id.up_vertex(),
+            new_b,
+            beam_data: self.beam_data,
+        }
+    }
+}
+
+/// A message tagged with it's position in a stream,
+/// so that it can be ordered after arriving out of order.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Sequenced<T> {
+    pub seq: u64,
+    pub inner: T,
+}
+
+/// Several updates sent as one message.
+///
+/// Can be created from the updates returned by [crate::server::FrameTransaction::commit].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FrameUpdateBatch<B, V = ()>(pub Vec<FrameUpdate<B, V>>);
+
+impl<B, V> Default for FrameUpdateBatch<B, V> {
+    fn default() -> Self {
+        FrameUpdateBatch(Vec::new())
+    }
+}
+
+impl<B, V> From<Vec<FrameUpdate<B, V>>> for FrameUpdateBatch<B, V> {
+    fn from(updates: Vec<FrameUpdate<B, V>>) -> Self {
+        FrameUpdateBatch(updates)
+    }
+}
+
+/// Creates the updates that turn a client built from `old` into `new`.
+///
+/// Vertices without any beams can't be added or removed with updates, so they are ignored.
+pub fn diff<B, V>(
+    old: &SerializedGraph<B, V>,
+    new: &SerializedGraph<B, V>,
+) -> Vec<FrameUpdate<B, V>>
+where
+    B: Clone + PartialEq,
+    V: Clone + PartialEq,
+{
+    let old_beams: HashMap<BeamId, &B> = old.beams.iter().map(|(id, data)| (*id, data)).collect();
+    let new_beams: HashMap<BeamId, &B> = new.beams.iter().map(|(id, data)| (*id, data)).collect();
+    let new_vertices: HashMap<VertexId, (Position, &V)> = new
+        .vertices
+        .iter()
+        .map(|(id, position, data)| (*id, (*position, data)))
+        .collect();
+
+    let mut updates = Vec::new();
+
+    // track which vertices the client has, as removing a vertex's last beam removes it
+    let mut present: HashSet<VertexId> = old.vertices.iter().map(|(id, ..)| *id).collect();
+    let mut degrees: HashMap<VertexId, usize> = HashMap::new();
+
+    for (id, _) in old.beams.iter() {
+        for vertex in [id.down_vertex(), id.up_vertex()] {
+            *degrees.entry(vertex).or_default() += 1;
+        }
+    }
+
+    for (id, _) in old.beams.iter() {
+        if new_beams.contains_key(id) {
+            continue;
+        }
+
+        updates.push(FrameUpdate::RemoveBeam {
+            id: *id,
+            recovered: None,
+        });
+
+        for vertex in [id.down_vertex(), id.up_vertex()] {
+            let degree = degrees.entry(vertex).or_default();
+            *degree -= 1;
+
+            if *degree == 0 {
+                present.remove(&vertex);
+            }
+        }
+    }
+
+    let kept = present.clone();
+
+    for (id, beam_data) in new.beams.iter() {
+        if old_beams.contains_key(id) {
+            continue;
+        }
+
+        let (vertex_a, vertex_b) = id.vertices();
+
+        let mut new_vertex = |vertex| {
+            present
+                .insert(vertex)
+                .then(|| {
+                    new_vertices
+                        .get(&vertex)
+                        .map(|&(position, data)| (position, data.clone()))
+                })
+                .flatten()
+        };
+
+        updates.push(FrameUpdate::AddBeam {
+            vertex_a,
+            new_a: new_vertex(vertex_a),
+            vertex_b,
+            new_b: new_vertex(vertex_b),
+            beam_data: beam_data.clone(),
+        });
+    }
+
+    for (id, old_position, old_data) in old.vertices.iter() {
+        let Some(&(position, data)) = new_vertices.get(id) else {
+            continue;
+        };
+
+        if !kept.contains(id) {
+            continue;
+        }
+
+        if position != *old_position {
+            updates.push(FrameUpdate::MoveVertex { id: *id, position });
+        }
+
+        if data != old_data {
+            updates.push(FrameUpdate::UpdateVertexData {
+                id: *id,
+                vertex_data: data.clone(),
+            });
+        }
+    }
+
+    for (id, beam_data) in new.beams.iter() {
+        if let Some(&old_data) = old_beams.get(id) {
+            if old_data != beam_data {
+                updates.push(FrameUpdate::UpdateBeamData {
+                    id: *id,
+                    beam_data: beam_data.clone(),
+                });
+            }
+        }
+    }
+
+    updates
+}
+
+/// Blends the vertex positions of two snapshots of the same frame,
+/// where a `t` of 0 gives `a`'s positions and 1 gives `b`'s.
+///
+/// The result keeps `a`'s vertices, vertex data, beams and beam data,
+/// vertices that aren't in `b` keep their position from `a`.
+pub fn interpolate<B: Clone, V: Clone>(
+    a: &SerializedGraph<B, V>,
+    b: &SerializedGraph<B, V>,
+    t: Scalar,
+) -> SerializedGraph<B, V> {
+    let b_positions: HashMap<VertexId, Position> = b
+        .vertices
+        .iter()
+        .map(|&(id, position, _)| (id, position))
+        .collect();
+
+    SerializedGraph {
+        vertices: a
+            .vertices
+            .iter()
+            .map(|(id, position, data)| match b_positions.get(id) {
+                Some(&b_position) => (*id, position.lerp(b_position, t), data.clone()),
+                None => (*id, *position, data.clone()),
+            })
+            .collect(),
+        beams: a.beams.clone(),
+    }
+}
+
+impl<B, V> FrameUpdate<B, V> {
+    /// Whether both updates make the same edit, ignoring the `recovered` data of a removal,
+    /// eg. to match an update from the server with a prediction that didn't have it.
+    pub fn same_edit(&self, other: &FrameUpdate<B, V>) -> bool
+    where
+        B: PartialEq,
+        V: PartialEq,
+    {
+        match (self, other) {
+            (FrameUpdate::RemoveBeam { id, .. }, FrameUpdate::RemoveBeam { id: other_id, .. }) => {
+                id == other_id
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Creates the update that reverts this one,
+    /// given the state of the graph before this update is applied.
+    ///
+    /// The inverse of removing a beam recreates any vertices that were removed along with it.
+    /// Applying an update and then it's inverse leaves the graph's vertices and beams unchanged.
+    ///
+    /// Panics if the update doesn't apply to the graph.
+    pub fn inverse(&self, graph: &Graph<B, V>) -> FrameUpdate<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        match *self {
+            FrameUpdate::AddBeam {
+                vertex_a,
+                ref new_a,
+                vertex_b,
+                ref new_b,
+                ref beam_data,
+            } => FrameUpdate::RemoveBeam {
+                id: BeamId::from_vertices(vertex_a, vertex_b),
+                recovered: Some(RemovedBeamData {
+                    beam_data: beam_data.clone(),
+                    removed_vertices: [(vertex_a, new_a), (vertex_b, new_b)]
+                        .into_iter()
+                        .filter_map(|(id, new)| {
+                            new.clone().map(|(position, data)| (id, position, data))
+                        })
+                        .collect(),
+                }),
+            },
+            FrameUpdate::RemoveBeam { id, .. } => {
+                let Some(removed) = graph.removed_beam_data(id) else {
+                    panic!("Tried to invert the removal of a beam that doesn't exist.");
+                };
+
+                removed.restore(id)
+            }
+            FrameUpdate::MoveVertex { id, .. } => {
+                let Some(vertex) = graph.get_vertex(id) else {
+                    panic!("Tried to invert moving a vertex that doesn't exist.");
+                };
+
+                FrameUpdate::MoveVertex {
+                    id,
+                    position: vertex.position,
+                }
+            }
+            FrameUpdate::UpdateBeamData { id, .. } => {
+                let Some(beam_data) = graph.get_beam(id) else {
+                    panic!("Tried to invert updating a beam that doesn't exist.");
+                };
+
+                FrameUpdate::UpdateBeamData {
+                    id,
+                    beam_data: beam_data.clone(),
+                }
+            }
+            FrameUpdate::UpdateVertexData { id, .. } => {
+                let Some(vertex) = graph.get_vertex(id) else {
+                    panic!("Tried to invert updating a vertex that doesn't exist.");
+                };
+
+                FrameUpdate::UpdateVertexData {
+                    id,
+                    vertex_data: vertex.data.clone(),
+                }
+            }
+        }
+    }
+}
+
+/// What applying a [FrameUpdate] overwrites, which is needed to unapply it,
+/// see [crate::client::ShipFrame::unapply_update].
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum UnapplyContext<B, V = ()> {
+    /// Adding a beam loses nothing, and neither does removing one that carries it's recovery data.
+    None,
+    /// What a [FrameUpdate::RemoveBeam] removed.
+    RemovedBeam(RemovedBeamData<B, V>),
+    /// The position before a [FrameUpdate::MoveVertex].
+    Position(Position),
+    /// The data before a [FrameUpdate::UpdateBeamData].
+    BeamData(B),
+    /// The data before a [FrameUpdate::UpdateVertexData].
+    VertexData(V),
+}
+
+impl<B, V> FrameUpdate<B, V> {
+    /// Captures what this update would overwrite in the graph, which has to be done before it's applied.
+    ///
+    /// Returns `None` if the update doesn't apply to the graph.
+    pub fn unapply_context(&self, graph: &Graph<B, V>) -> Option<UnapplyContext<B, V>>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        match *self {
+            FrameUpdate::AddBeam { .. } => Some(UnapplyContext::None),
+            FrameUpdate::RemoveBeam { id, .. } => {
+                graph.removed_beam_data(id).map(UnapplyContext::RemovedBeam)
+            }
+            FrameUpdate::MoveVertex { id, .. } => graph
+                .get_vertex(id)
+                .map(|vertex| UnapplyContext::Position(vertex.position)),
+            FrameUpdate::UpdateBeamData { id, .. } => graph
+                .get_beam(id)
+                .map(|beam_data| UnapplyContext::BeamData(beam_data.clone())),
+            FrameUpdate::UpdateVertexData { id, .. } => graph
+                .get_vertex(id)
+                .map(|vertex| UnapplyContext::VertexData(vertex.data.clone())),
+        }
+    }
+
+    /// Creates the update that reverts this one from what it overwrote.
+    pub(crate) fn inverse_from_context(
+        &self,
+        context: &UnapplyContext<B, V>,
+    ) -> Result<FrameUpdate<B, V>, ApplyError>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let inverse = match (self, context) {
+            (
+                &FrameUpdate::AddBeam {
+                    vertex_a, vertex_b, ..
+                },
+                _,
+            ) => FrameUpdate::RemoveBeam {
+                id: BeamId::from_vertices(vertex_a, vertex_b),
+                recovered: None,
+            },
+            (
+                FrameUpdate::RemoveBeam {
+                    id,
+                    recovered: Some(removed),
+                },
+                _,
+            )
+            | (FrameUpdate::RemoveBeam { id, .. }, UnapplyContext::RemovedBeam(removed)) => {
+                removed.clone().restore(*id)
+            }
+            (FrameUpdate::MoveVertex { id, .. }, &UnapplyContext::Position(position)) => {
+                FrameUpdate::MoveVertex { id: *id, position }
+            }
+            (FrameUpdate::UpdateBeamData { id, .. }, UnapplyContext::BeamData(beam_data)) => {
+                FrameUpdate::UpdateBeamData {
+                    id: *id,
+                    beam_data: beam_data.clone(),
+                }
+            }
+            (FrameUpdate::UpdateVertexData { id, .. }, UnapplyContext::VertexData(vertex_data)) => {
+                FrameUpdate::UpdateVertexData {
+                    id: *id,
+                    vertex_data: vertex_data.clone(),
+                }
+            }
+            _ => return Err(ApplyError::MissingContext),
+        };
+
+        Ok(inverse)
+    }
+}
+
+/// The reasons a [FrameUpdate] can't be applied to a graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApplyError {
+    /// The beam of an [FrameUpdate::AddBeam] can't be inserted.
+    AddBeam(AddBeamError),
+    /// The update references a beam that isn't in the graph.
+    MissingBeam(BeamId),
+    /// The update references a vertex that isn't in the graph.
+    MissingVertex(VertexId),
+    /// A [FrameUpdate::MoveVertex] has a NaN or infinite component.
+    NonFinitePosition(VertexId),
+    /// Too many updates arrived ahead of a missing one,
+    /// see [crate::client::MAX_BUFFERED_UPDATES].
+    ///
+    /// The buffered updates are discarded but the client still waits for the missing one,
+    /// so it can only move forward again after a reset from a snapshot with it's sequence number,
+    /// see [crate::client::ShipFrame::reset_from].
+    TooManyBuffered,
+    /// The [UnapplyContext] given to [crate::client::ShipFrame::unapply_update]
+    /// doesn't hold what the update overwrote.
+    MissingContext,
+}
+
+/// What applying a [FrameUpdate] changed in the structure of a graph.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateEffect {
+    /// Vertices inserted by a [FrameUpdate::AddBeam].
+    pub created_vertices: Vec<VertexId>,
+    /// The beam inserted by a [FrameUpdate::AddBeam].
+    pub created_beam: Option<BeamId>,
+    /// Vertices removed by a [FrameUpdate::RemoveBeam] because it was their last connection.
+    pub removed_vertices: Vec<VertexId>,
+}
+
+/// The first update of a [FrameUpdateBatch] that couldn't be applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchApplyError {
+    /// The index of the update in the batch.
+    pub index: usize,
+    pub error: ApplyError,
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApplyError::AddBeam(_) => write!(f, "can't add beam"),
+            ApplyError::MissingBeam(id) => write!(f, "beam {:?} doesn't exist", id),
+            ApplyError::MissingVertex(id) => write!(f, "vertex {:?} doesn't exist", id),
+            ApplyError::NonFinitePosition(id) => {
+                write!(f, "vertex {:?} can't move to a non finite position", id)
+            }
+            ApplyError::TooManyBuffered => write!(f, "too many updates buffered"),
+            ApplyError::MissingContext => write!(f, "missing context to unapply update"),
+        }
+    }
+}
+
+impl std::error::Error for ApplyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApplyError::AddBeam(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BatchApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "update {} of the batch failed to apply", self.index)
+    }
+}
+
+impl std::error::Error for BatchApplyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<B, V> Graph<B, V> {
+    /// Applies an update to the graph.
+    ///
+    /// The graph is left unmodified if an error is returned.
+    pub(crate) fn apply_update(&mut self, update: FrameUpdate<B, V>) -> Result<(), ApplyError> {
+        self.apply_update_reporting(update).map(|_| ())
+    }
+
+    /// What removing a beam would remove, or `None` if the beam isn't in the graph.
+    pub(crate) fn removed_beam_data(&self, id: BeamId) -> Option<RemovedBeamData<B, V>>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let beam_data = self.get_beam(id)?.clone();
+
+        let removed_vertices = [id.down_vertex(), id.up_vertex()]
+            .into_iter()
+            .filter_map(|vertex_id| {
+                let vertex = &self.vertices[&vertex_id];
+                (vertex.connections.len() == 1)
+                    .then(|| (vertex_id, vertex.position, vertex.data.clone()))
+            })
+            .collect();
+
+        Some(RemovedBeamData {
+            beam_data,
+            removed_vertices,
+        })
+    }
+
+    /// Same as [Graph::apply_update] but also returns what the update changed.
+    pub(crate) fn apply_update_reporting(
+        &mut self,
+        update: FrameUpdate<B, V>,
+    ) -> Result<UpdateEffect, ApplyError> {
+        let mut effect = UpdateEffect::default();
+
+        match update {
+            FrameUpdate::AddBeam {
+                vertex_a,
+                new_a,
+                vertex_b,
+                new_b,
+                beam_data,
+            } => {
+                let created = [(vertex_a, new_a.is_some()), (vertex_b, new_b.is_some())];
+
+                self.add_beam_with_data(vertex_a, new_a, vertex_b, new_b, beam_data)
+                    .map_err(ApplyError::AddBeam)?;
+
+                effect.created_vertices = created
+                    .into_iter()
+                    .filter_map(|(id, new)| new.then_some(id))
+                    .collect();
+                effect.created_beam = Some(BeamId::from_vertices(vertex_a, vertex_b));
+            }
+            FrameUpdate::RemoveBeam { id, .. } => {
+                if self.get_beam(id).is_none() {
+                    return Err(ApplyError::MissingBeam(id));
+                }
+
+                effect.removed_vertices = [id.down_vertex(), id.up_vertex()]
+                    .into_iter()
+                    .filter(|&vertex| self.degree(vertex) == Some(1))
+                    .collect();
+
+                self.remove_beam(id);
+            }
+            FrameUpdate::MoveVertex { id, position } => {
+                if self.get_vertex(id).is_none() {
+                    return Err(ApplyError::MissingVertex(id));
+                }
+
+                if !position.is_finite() {
+                    return Err(ApplyError::NonFinitePosition(id));
+                }
+
+                self.set_vertex_position(id, position);
+            }
+            FrameUpdate::UpdateBeamData { id, beam_data } => {
+                let Some(existing) = self.get_beam_mut(id) else {
+                    return Err(ApplyError::MissingBeam(id));
+                };
+
+                *existing = beam_data;
+            }
+            FrameUpdate::UpdateVertexData { id, vertex_data } => {
+                let Some(vertex) = self.get_vertex_mut(id) else {
+                    return Err(ApplyError::MissingVertex(id));
+                };
+
+                vertex.data = vertex_data;
+            }
+        }
+
+        Ok(effect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client, server::FrameIdWorld};
+
+    #[test]
+    fn diff_turns_old_into_new() {
+        let mut id_world = FrameIdWorld::default();
+        let [a, b, c, d, e] = [(); 5].map(|_| id_world.next());
+        let beam = BeamId::from_vertices;
+
+        let old = SerializedGraph {
+            vertices: vec![
+                (a, Position::ZERO, 1u8),
+                (b, Position::X, 2),
+                (c, Position::Y, 3),
+                (d, Position::Z, 4),
+            ],
+            beams: vec![
+                (beam(a, b), 10u32),
+                (beam(b, c), 20),
+                (beam(a, c), 30),
+                (beam(c, d), 40),
+            ],
+        };
+
+        // d is removed with it's only beam, e is added, b and c move and some data changes
+        let new = SerializedGraph {
+            vertices: vec![
+                (a, Position::ZERO, 1u8),
+                (b, Position::X * 2., 2),
+                (c, Position::NEG_Y, 9),
+                (e, Position::ONE, 5),
+            ],
+            beams: vec![
+                (beam(a, b), 10u32),
+                (beam(b, c), 25),
+                (beam(c, e), 50),
+                (beam(a, e), 60),
+            ],
+        };
+
+        let mut client = client::ShipFrame::new(old.clone());
+
+        for update in diff(&old, &new) {
+            client.apply_update(update).unwrap();
+        }
+
+        assert!(*client.graph() == new.try_into_graph().unwrap());
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bytes_round_trip() {
+        let mut id_world = FrameIdWorld::default();
+        let [a, b, c, d] = [(); 4].map(|_| id_world.next());
+
+        // every vertex is shared by at least two beams
+        let mut graph = Graph::<u32, u8>::default();
+        graph
+            .add_beam_with_data(a, Some((Position::ZERO, 1)), b, Some((Position::X, 2)), 10)
+            .unwrap();
+        graph
+            .add_beam_with_data(b, None, c, Some((Position::Y, 3)), 20)
+            .unwrap();
+        graph.add_beam_with_data(c, None, a, None, 30).unwrap();
+        graph
+            .add_beam_with_data(a, None, d, Some((Position::Z, 4)), 40)
+            .unwrap();
+        graph.add_beam_with_data(d, None, b, None, 50).unwrap();
+
+        let bytes = SerializedGraph::from(&graph).to_bytes();
+        let decoded = SerializedGraph::<u32, u8>::from_bytes(&bytes)
+            .unwrap()
+            .try_into_graph()
+            .unwrap();
+
+        assert!(decoded == graph);
+        assert_eq!(decoded.vertices[&a].connections.len(), 3);
+
+        let streamed = Graph::<u32, u8>::from_reader(bytes.as_slice()).unwrap();
+        assert!(streamed == graph);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn columnar_groups_beam_data() {
+        let mut id_world = FrameIdWorld::default();
+        let mut graph = Graph::<u32>::default();
+        let mut previous = id_world.next();
+        graph
+            .add_beam(
+                previous,
+                Some(Position::ZERO),
+                id_world.next(),
+                Some(Position::X),
+                7,
+            )
+            .unwrap();
+
+        for step in 1..100 {
+            let next = id_world.next();
+            graph
+                .add_beam(previous, None, next, Some(Position::Y * step as Scalar), 7)
+                .unwrap();
+            previous = next;
+        }
+
+        let serialized = SerializedGraph::from(&graph);
+        let columnar = serialized.to_columnar();
+        assert_eq!(columnar.beam_data(), [7; 100]);
+
+        let interleaved_bytes = bincode::serialize(&serialized).unwrap();
+        let columnar_bytes = bincode::serialize(&columnar).unwrap();
+
+        // three more list lengths, each a u64
+        assert_eq!(columnar_bytes.len(), interleaved_bytes.len() + 3 * 8);
+
+        // the beam data is encoded last, as one run of identical values
+        let beam_data_bytes = bincode::serialize(&7u32).unwrap().repeat(100);
+        assert!(columnar_bytes.ends_with(&beam_data_bytes));
+
+        let round_tripped = SerializedGraph::from_columnar(columnar)
+            .unwrap()
+            .try_into_graph()
+            .unwrap();
+        assert!(round_tripped == graph);
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+
+use crate::{graph::Graph, Position, VertexId};
+
+impl<B: Sync, V: Sync> Graph<B, V> {
+    /// Same as [Graph::bounds] but computed in parallel, for very large frames.
+    pub fn par_bounds(&self) -> Option<(Position, Position)> {
+        self.vertices
+            .par_values()
+            .map(|vertex| (vertex.position, vertex.position))
+            .reduce_with(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+    }
+
+    /// Same as [Graph::connected_components] but joins the ends of every beam in parallel, for very large frames.
+    ///
+    /// The groups are in the same order, but the vertices of each group are in insertion order instead.
+    pub fn par_connected_components(&self) -> Vec<Vec<VertexId>> {
+        // each vertex points at a vertex with a lower index in the same group, roots point at themselves
+        let parents: Vec<AtomicUsize> = (0..self.vertices.len()).map(AtomicUsize::new).collect();
+
+        self.beams.par_keys().for_each(|id| {
+            let down = self.vertices.get_index_of(&id.down_vertex()).unwrap();
+            let up = self.vertices.get_index_of(&id.up_vertex()).unwrap();
+
+            union(&parents, down, up);
+        });
+
+        let roots: Vec<usize> = (0..parents.len())
+            .into_par_iter()
+            .map(|index| find(&parents, index))
+            .collect();
+
+        // a group's root is it's first vertex, so groups are created in the order of their first vertex
+        let mut group_of_root = vec![usize::MAX; roots.len()];
+        let mut components: Vec<Vec<VertexId>> = Vec::new();
+
+        for (index, (&id, _)) in self.vertices.iter().enumerate() {
+            let root = roots[index];
+
+            if group_of_root[root] == usize::MAX {
+                group_of_root[root] = components.len();
+                components.push(Vec::new());
+            }
+
+            components[group_of_root[root]].push(id);
+        }
+
+        components
+    }
+}
+
+fn find(parents: &[AtomicUsize], mut index: usize) -> usize {
+    loop {
+        let parent = parents[index].load(Ordering::Acquire);
+
+        if parent == index {
+            return index;
+        }
+
+        // path halving, the grandparent is still in the same group so a failed swap is harmless
+        let grandparent = parents[parent].load(Ordering::Acquire);
+        let _ = parents[index].compare_exchange_weak(
+            parent,
+            grandparent,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+
+        index = grandparent;
+    }
+}
+
+fn union(parents: &[AtomicUsize], a: usize, b: usize) {
+    loop {
+        let root_a = find(parents, a);
+        let root_b = find(parents, b);
+
+        if root_a == root_b {
+            return;
+        }
+
+        // always link the higher root below the lower one, which fails if it stopped being a root
+        let (high, low) = (root_a.max(root_b), root_a.min(root_b));
+
+        if parents[high]
+            .compare_exchange(high, low, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+    }
+}
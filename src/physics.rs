@@ -0,0 +1,127 @@
+use bevy::utils::HashMap;
+use nalgebra::{DMatrix, DVector};
+
+use crate::{graph::Graph, BeamId, Position, Scalar, VertexId};
+
+/// Singular values of the stiffness matrix smaller than this fraction of the largest
+/// are treated as zero, as they belong to ways the frame can move without stretching any beam.
+const SINGULAR_TOLERANCE: f64 = 1e-10;
+
+/// Solves the graph as a pin jointed truss, returning the axial force in every beam
+/// caused by the given external loads at vertices. Positive forces are tension, negative compression.
+///
+/// `stiffness` gives the axial rigidity of a beam, eg. Young's modulus times cross section area,
+/// which is divided by the beam's length to get it's spring constant.
+///
+/// A frame floating in space isn't held in place, so any part of the loads that would
+/// accelerate or spin the frame (or a mechanism in it) as a whole is ignored,
+/// which is exact when the loads are balanced.
+///
+/// Loads on vertices that aren't in the graph are ignored and beams with zero length carry no force.
+/// The solve is dense, taking cubic time in the number of vertices.
+pub fn axial_forces<B, V>(
+    graph: &Graph<B, V>,
+    loads: &HashMap<VertexId, Position>,
+    stiffness: impl Fn(&B) -> Scalar,
+) -> HashMap<BeamId, Scalar> {
+    let size = graph.vertices.len() * 3;
+
+    // (beam, index of the down vertex, index of the up vertex, unit direction, spring constant)
+    let mut elements = Vec::with_capacity(graph.beams.len());
+
+    for (&id, beam_data) in graph.beams.iter() {
+        let down = graph.vertices.get_index_of(&id.down_vertex()).unwrap();
+        let up = graph.vertices.get_index_of(&id.up_vertex()).unwrap();
+
+        let vector = to_array(graph.vertices[up].position - graph.vertices[down].position);
+        let length = vector.iter().map(|value| value * value).sum::<f64>().sqrt();
+
+        if length == 0. {
+            continue;
+        }
+
+        let direction = vector.map(|value| value / length);
+        let spring = to_f64(stiffness(beam_data)) / length;
+
+        elements.push((id, down, up, direction, spring));
+    }
+
+    let mut matrix = DMatrix::<f64>::zeros(size, size);
+
+    for &(_, down, up, direction, spring) in elements.iter() {
+        for a in 0..3 {
+            for b in 0..3 {
+                let value = spring * direction[a] * direction[b];
+
+                matrix[(down * 3 + a, down * 3 + b)] += value;
+                matrix[(up * 3 + a, up * 3 + b)] += value;
+                matrix[(down * 3 + a, up * 3 + b)] -= value;
+                matrix[(up * 3 + a, down * 3 + b)] -= value;
+            }
+        }
+    }
+
+    let mut forces = DVector::<f64>::zeros(size);
+
+    for (id, load) in loads.iter() {
+        let Some(index) = graph.vertices.get_index_of(id) else {
+            continue;
+        };
+
+        for (axis, value) in to_array(*load).into_iter().enumerate() {
+            forces[index * 3 + axis] += value;
+        }
+    }
+
+    let displacements = if size == 0 {
+        forces
+    } else {
+        let svd = matrix.svd(true, true);
+        let tolerance = svd.singular_values.max() * SINGULAR_TOLERANCE;
+        svd.solve(&forces, tolerance)
+            .expect("Both singular vectors were computed.")
+    };
+
+    let mut axial = HashMap::with_capacity(graph.beams.len());
+
+    for &id in graph.beams.keys() {
+        axial.insert(id, 0.);
+    }
+
+    for (id, down, up, direction, spring) in elements {
+        let stretch: f64 = (0..3)
+            .map(|axis| {
+                direction[axis] * (displacements[up * 3 + axis] - displacements[down * 3 + axis])
+            })
+            .sum();
+
+        axial.insert(id, to_scalar(spring * stretch));
+    }
+
+    axial
+}
+
+fn to_array(position: Position) -> [f64; 3] {
+    position.to_array().map(to_f64)
+}
+
+/// The solve is always done in double precision.
+#[cfg(not(feature = "f64"))]
+fn to_f64(value: Scalar) -> f64 {
+    value as f64
+}
+
+#[cfg(feature = "f64")]
+fn to_f64(value: Scalar) -> f64 {
+    value
+}
+
+#[cfg(not(feature = "f64"))]
+fn to_scalar(value: f64) -> Scalar {
+    value as f32
+}
+
+#[cfg(feature = "f64")]
+fn to_scalar(value: f64) -> Scalar {
+    value
+}
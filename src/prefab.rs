@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ids::{FrameIdAllocator, IdSource},
+    messages::SerializedGraph,
+    server::ShipFrame,
+};
+
+/// A frame blueprint that isn't tied to any [IdSource].
+///
+/// Its ids are local to the prefab and start at 0.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(transparent)]
+pub struct Prefab<B, V = ()> {
+    graph: SerializedGraph<B, V>,
+}
+
+impl<B, V> Prefab<B, V> {
+    /// Creates a prefab from a graph, rewriting its ids to be local to the prefab.
+    pub fn new(graph: SerializedGraph<B, V>) -> Self {
+        Prefab {
+            graph: FrameIdAllocator::default().map_graph(graph),
+        }
+    }
+
+    /// The prefab's graph, with prefab local ids.
+    pub fn graph(&self) -> &SerializedGraph<B, V> {
+        &self.graph
+    }
+
+    /// Consumes the prefab, returning it's graph.
+    pub fn into_graph(self) -> SerializedGraph<B, V> {
+        self.graph
+    }
+}
+
+impl<B: Clone, V: Clone> Prefab<B, V> {
+    /// Creates a new frame from this prefab with ids from `id_world`.
+    pub fn instantiate(&self, id_world: &mut impl IdSource) -> ShipFrame<B, V> {
+        id_world.map_frame(self.graph.clone())
+    }
+}
+
+impl<B, V> From<SerializedGraph<B, V>> for Prefab<B, V> {
+    fn from(graph: SerializedGraph<B, V>) -> Self {
+        Prefab::new(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BeamId, Position, VertexId};
+
+    #[test]
+    fn instantiating_twice_is_identical() {
+        let mut id_source = FrameIdAllocator::default();
+        let [a, b, c, d] = [(); 4].map(|_| id_source.next());
+
+        // vertices and beams listed out of id order, so the vertex and beam passes disagree
+        let graph = SerializedGraph {
+            vertices: vec![
+                (d, Position::Z, 4u8),
+                (b, Position::X, 2),
+                (c, Position::Y, 3),
+                (a, Position::ZERO, 1),
+            ],
+            beams: vec![
+                (BeamId::from_vertices(c, d), 30u32),
+                (BeamId::from_vertices(a, b), 10),
+                (BeamId::from_vertices(b, c), 20),
+            ],
+        };
+        let prefab = Prefab::new(graph);
+
+        let first = prefab.instantiate(&mut FrameIdAllocator::default());
+        let second = prefab.instantiate(&mut FrameIdAllocator::default());
+
+        assert!(first.graph() == second.graph());
+
+        // new ids are given out in ascending order of the original ids
+        let mut vertices: Vec<(VertexId, u8)> = first
+            .iter_vertices()
+            .map(|(id, vertex)| (id, *vertex.data()))
+            .collect();
+        vertices.sort_unstable_by_key(|&(id, _)| id);
+        assert_eq!(
+            vertices.iter().map(|&(_, data)| data).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+    }
+}
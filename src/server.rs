@@ -1,58 +1,162 @@
-use bevy::{prelude::*, utils::HashMap};
+use std::{collections::BTreeMap, fmt, marker::PhantomData};
+
+use serde::{Deserialize, Serialize};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 
 use crate::{
     graph::*,
-    messages::{FrameUpdate, SerializedGraph},
-    BeamId, VertexId,
+    messages::{
+        ApplyError, FrameUpdate, RemovedBeamData, Sequenced, SerializedGraph, ValidationError,
+    },
+    BeamId, Position, Scalar, VertexId,
 };
 
-#[derive(Resource, Default)]
-pub struct FrameIdWorld {
-    next_id: u64,
+pub use crate::ids::{FrameIdAllocator as FrameIdWorld, IdSource};
+
+/// The reasons two [ShipFrame]s can't be merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    /// A vertex id exists in both frames.
+    IdCollision(VertexId),
+    /// One of the joining beams can't be inserted.
+    InvalidJoin(AddBeamError),
+    /// The merged frame would have more beams than this frame's limit.
+    LimitExceeded,
 }
 
-impl FrameIdWorld {
-    pub fn next(&mut self) -> VertexId {
-        let id = VertexId(self.next_id);
-        self.next_id += 1;
-        id
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::IdCollision(id) => write!(f, "vertex {:?} is in both frames", id),
+            MergeError::InvalidJoin(_) => write!(f, "can't add joining beam"),
+            MergeError::LimitExceeded => write!(f, "merged frame would exceed the beam limit"),
+        }
     }
+}
 
-    /// Maps a frame graph into this id id_world's scope,
-    /// ensuring that ids within the graph stay consistent,
-    /// but don't reference any existing ids.
-    pub fn map_frame<B>(&mut self, mut graph: SerializedGraph<B>) -> ShipFrame<B> {
-        let mut map = HashMap::new();
-
-        for (id, _) in graph.vertices.iter_mut() {
-            *id = *map.entry(*id).or_insert_with(|| self.next());
+impl std::error::Error for MergeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MergeError::InvalidJoin(error) => Some(error),
+            _ => None,
         }
+    }
+}
 
-        for (id, _) in graph.beams.iter_mut() {
-            let (mut id_a, mut id_b) = id.vertices();
-
-            id_a = *map.entry(id_a).or_insert_with(|| self.next());
-            id_b = *map.entry(id_b).or_insert_with(|| self.next());
-
-            *id = BeamId::from_vertices(id_a, id_b)
-        }
+/// The reasons a beam can't be split with [ShipFrame::split_beam].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitBeamError {
+    MissingBeam(BeamId),
+    /// The split point is further from the beam than the tolerance, or isn't finite.
+    OffSegment,
+    /// Splitting the beam would pass the frame's beam limit.
+    LimitExceeded,
+}
 
-        ShipFrame {
-            graph: graph.into(),
+impl fmt::Display for SplitBeamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SplitBeamError::MissingBeam(id) => write!(f, "beam {:?} doesn't exist", id),
+            SplitBeamError::OffSegment => write!(f, "split point isn't on the beam"),
+            SplitBeamError::LimitExceeded => write!(f, "split would exceed the beam limit"),
         }
     }
 }
 
+impl std::error::Error for SplitBeamError {}
+
+/// The authoritative copy of a frame, see [crate::client::ShipFrame] for the client's copy.
+///
+/// Every edit is recorded so that it can be sent as a [ServerFrameEvent] by [ServerFramePlugin].
+/// Recorded edits are numbered in order starting from 0.
 #[derive(Component)]
-pub struct ShipFrame<B> {
-    graph: Graph<B>,
+pub struct ShipFrame<B, V = ()> {
+    graph: Graph<B, V>,
+    updates: Vec<Sequenced<FrameUpdate<B, V>>>,
+    next_seq: u64,
+    max_beams: Option<usize>,
 }
 
 impl<B> ShipFrame<B> {
+    /// Creates a frame with a single beam between two new vertices.
+    ///
+    /// Only for frames without vertex data, see [ShipFrame::new_from_beam_with_data].
+    /// Panics if either position isn't finite.
     pub fn new_from_beam(
-        id_world: &mut FrameIdWorld,
-        position_a: Vec3,
-        position_b: Vec3,
+        id_world: &mut impl IdSource,
+        position_a: Position,
+        position_b: Position,
+        beam_data: B,
+    ) -> Self {
+        Self::new_from_beam_with_data(id_world, (position_a, ()), (position_b, ()), beam_data)
+    }
+}
+
+impl<B, V> ShipFrame<B, V> {
+    pub(crate) fn from_graph(graph: Graph<B, V>) -> Self {
+        ShipFrame {
+            graph,
+            updates: Vec::new(),
+            next_seq: 0,
+            max_beams: None,
+        }
+    }
+
+    /// Limits how many beams the frame can have,
+    /// adding beams past the limit returns [AddBeamError::LimitExceeded].
+    ///
+    /// Existing beams are kept, the limit only prevents adding more.
+    pub fn with_limit(mut self, max_beams: usize) -> Self {
+        self.max_beams = Some(max_beams);
+        self
+    }
+
+    /// If `count` more beams can be added without passing the limit.
+    fn within_limit(&self, count: usize) -> bool {
+        self.max_beams
+            .is_none_or(|max_beams| self.graph.beam_count() + count <= max_beams)
+    }
+
+    fn record(&mut self, update: &FrameUpdate<B, V>)
+    where
+        B: Clone,
+        V: Clone,
+    {
+        self.push_update(update.clone());
+    }
+
+    fn push_update(&mut self, update: FrameUpdate<B, V>) {
+        self.updates.push(Sequenced {
+            seq: self.next_seq,
+            inner: update,
+        });
+        self.next_seq += 1;
+    }
+
+    /// Creates a frame from a graph whose ids are already in the scope of the [IdSource],
+    /// eg. a saved live frame. Use [IdSource::map_frame] for graphs with ids from elsewhere.
+    ///
+    /// Panics if the graph is invalid, use [ShipFrame::try_from_serialized] for untrusted input.
+    pub fn from_serialized(serialized: SerializedGraph<B, V>) -> Self {
+        ShipFrame::from_graph(serialized.into())
+    }
+
+    /// Same as [ShipFrame::from_serialized] but returns an error instead of panicking if the graph is invalid.
+    pub fn try_from_serialized(serialized: SerializedGraph<B, V>) -> Result<Self, ValidationError> {
+        Ok(ShipFrame::from_graph(serialized.try_into_graph()?))
+    }
+
+    /// Creates a frame with a single beam between two new vertices with the given data.
+    ///
+    /// Panics if either position isn't finite.
+    pub fn new_from_beam_with_data(
+        id_world: &mut impl IdSource,
+        new_a: (Position, V),
+        new_b: (Position, V),
         beam_data: B,
     ) -> Self {
         let mut graph = Graph::default();
@@ -60,75 +164,1053 @@ impl<B> ShipFrame<B> {
         let vertex_a = id_world.next();
         let vertex_b = id_world.next();
 
-        graph.add_beam(
-            vertex_a,
-            Some(position_a),
-            vertex_b,
-            Some(position_b),
-            beam_data,
-        );
+        graph
+            .add_beam_with_data(vertex_a, Some(new_a), vertex_b, Some(new_b), beam_data)
+            .unwrap_or_else(|error| panic!("Tried to insert an invalid beam: {:?}", error));
 
-        ShipFrame { graph }
+        ShipFrame::from_graph(graph)
     }
 
-    pub fn add_beam_extend(
+    /// Adds a beam from an existing vertex to a new vertex with default vertex data.
+    ///
+    /// The frame is left unmodified if an error is returned, and no id is taken from `id_world`.
+    /// As the new vertex doesn't have an id yet, a non finite `position` is reported
+    /// with the id of the existing vertex.
+    pub fn try_add_beam_extend(
         &mut self,
-        id_world: &mut FrameIdWorld,
+        id_world: &mut impl IdSource,
         existing_vertex: VertexId,
-        position: Vec3,
+        position: Position,
         beam_data: B,
-    ) -> FrameUpdate<B>
+    ) -> Result<FrameUpdate<B, V>, AddBeamError>
     where
         B: Clone,
+        V: Clone + Default,
     {
+        self.try_add_beam_extend_with_data(
+            id_world,
+            existing_vertex,
+            position,
+            V::default(),
+            beam_data,
+        )
+    }
+
+    /// Same as [ShipFrame::try_add_beam_extend] with the data of the new vertex.
+    pub fn try_add_beam_extend_with_data(
+        &mut self,
+        id_world: &mut impl IdSource,
+        existing_vertex: VertexId,
+        position: Position,
+        vertex_data: V,
+        beam_data: B,
+    ) -> Result<FrameUpdate<B, V>, AddBeamError>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        if !self.within_limit(1) {
+            return Err(AddBeamError::LimitExceeded);
+        }
+
+        if self.graph.get_vertex(existing_vertex).is_none() {
+            return Err(AddBeamError::MissingVertex(existing_vertex));
+        }
+
+        if !position.is_finite() {
+            return Err(AddBeamError::NonFinitePosition(existing_vertex));
+        }
+
         let new_vertex = id_world.next();
 
-        self.graph.add_beam(
+        self.graph.add_beam_with_data(
             existing_vertex,
             None,
             new_vertex,
-            Some(position),
+            Some((position, vertex_data.clone())),
             beam_data.clone(),
-        );
+        )?;
 
-        FrameUpdate::AddBeam {
+        let update = FrameUpdate::AddBeam {
             vertex_a: existing_vertex,
-            position_a: None,
+            new_a: None,
             vertex_b: new_vertex,
-            position_b: Some(position),
+            new_b: Some((position, vertex_data)),
             beam_data,
-        }
+        };
+
+        self.record(&update);
+        Ok(update)
     }
 
-    pub fn add_beam_join(
+    /// Same as [ShipFrame::try_add_beam_extend] for input that is already known to be valid.
+    ///
+    /// Panics if the existing vertex isn't in the frame.
+    pub fn add_beam_extend(
+        &mut self,
+        id_world: &mut impl IdSource,
+        existing_vertex: VertexId,
+        position: Position,
+        beam_data: B,
+    ) -> FrameUpdate<B, V>
+    where
+        B: Clone,
+        V: Clone + Default,
+    {
+        self.try_add_beam_extend(id_world, existing_vertex, position, beam_data)
+            .unwrap_or_else(|error| panic!("Tried to insert an invalid beam: {:?}", error))
+    }
+
+    /// Adds a beam between two existing vertices.
+    ///
+    /// The frame is left unmodified if an error is returned.
+    pub fn try_add_beam_join(
         &mut self,
         vertex_a: VertexId,
         vertex_b: VertexId,
         beam_data: B,
-    ) -> FrameUpdate<B>
+    ) -> Result<FrameUpdate<B, V>, AddBeamError>
     where
         B: Clone,
+        V: Clone,
     {
+        if !self.within_limit(1) {
+            return Err(AddBeamError::LimitExceeded);
+        }
+
         self.graph
-            .add_beam(vertex_a, None, vertex_b, None, beam_data.clone());
+            .add_beam_with_data(vertex_a, None, vertex_b, None, beam_data.clone())?;
 
-        FrameUpdate::AddBeam {
+        let update = FrameUpdate::AddBeam {
             vertex_a,
-            position_a: None,
+            new_a: None,
             vertex_b,
-            position_b: None,
+            new_b: None,
             beam_data,
+        };
+
+        self.record(&update);
+        Ok(update)
+    }
+
+    /// Same as [ShipFrame::try_add_beam_join] for input that is already known to be valid.
+    ///
+    /// Panics if either vertex isn't in the frame or they are already connected.
+    pub fn add_beam_join(
+        &mut self,
+        vertex_a: VertexId,
+        vertex_b: VertexId,
+        beam_data: B,
+    ) -> FrameUpdate<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        self.try_add_beam_join(vertex_a, vertex_b, beam_data)
+            .unwrap_or_else(|error| panic!("Tried to insert an invalid beam: {:?}", error))
+    }
+
+    /// Panics if the vertex is not in the frame or the position isn't finite.
+    pub fn move_vertex(&mut self, id: VertexId, position: Position) -> FrameUpdate<B, V> {
+        self.graph.set_vertex_position(id, position);
+        self.push_update(FrameUpdate::MoveVertex { id, position });
+
+        FrameUpdate::MoveVertex { id, position }
+    }
+
+    /// Applies an update, such as the inverse of a previous edit to undo it.
+    ///
+    /// The frame is left unmodified if an error is returned.
+    pub fn apply_update(&mut self, update: FrameUpdate<B, V>) -> Result<(), ApplyError>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        if matches!(update, FrameUpdate::AddBeam { .. }) && !self.within_limit(1) {
+            return Err(ApplyError::AddBeam(AddBeamError::LimitExceeded));
+        }
+
+        self.graph.apply_update(update.clone())?;
+        self.push_update(update);
+        Ok(())
+    }
+
+    /// Replaces the data of an existing beam.
+    ///
+    /// Panics if the beam is not in the graph.
+    pub fn update_beam_data(&mut self, id: BeamId, beam_data: B) -> FrameUpdate<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let Some(existing) = self.graph.get_beam_mut(id) else {
+            panic!("Tried to update a beam that doesn't exist.");
+        };
+
+        *existing = beam_data.clone();
+
+        let update = FrameUpdate::UpdateBeamData { id, beam_data };
+        self.record(&update);
+        update
+    }
+
+    /// Replaces the data of an existing vertex.
+    ///
+    /// Panics if the vertex is not in the graph.
+    pub fn update_vertex_data(&mut self, id: VertexId, vertex_data: V) -> FrameUpdate<B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let Some(vertex) = self.graph.get_vertex_mut(id) else {
+            panic!("Tried to update a vertex that doesn't exist.");
+        };
+
+        *vertex.data_mut() = vertex_data.clone();
+
+        let update = FrameUpdate::UpdateVertexData { id, vertex_data };
+        self.record(&update);
+        update
+    }
+
+    /// Removes every beam that intersects a sphere,
+    /// including beams that only cross the sphere's boundary, see [Graph::beams_in_radius].
+    ///
+    /// Vertices left without any connections are removed.
+    /// Returns the updates to send to clients.
+    pub fn remove_beams_in_sphere(
+        &mut self,
+        center: Position,
+        radius: Scalar,
+    ) -> Vec<FrameUpdate<B, V>>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        self.graph
+            .beams_in_radius(center, radius)
+            .into_iter()
+            .map(|id| {
+                let update = FrameUpdate::RemoveBeam {
+                    id,
+                    recovered: self.graph.removed_beam_data(id),
+                };
+
+                self.graph.remove_beam(id);
+                self.record(&update);
+                update
+            })
+            .collect()
+    }
+
+    /// Replaces a beam with two beams joined at a new vertex with default data,
+    /// eg. to subdivide a beam so other beams can be attached in it's middle.
+    ///
+    /// `at` has to be within `tolerance` of the beam. `data_low` is given to the beam
+    /// from the down vertex to the new one and `data_high` to the beam from the new one to the up vertex.
+    ///
+    /// Returns the updates to send to clients, which add both beams and then remove the old one.
+    /// The frame is left unmodified if an error is returned.
+    pub fn split_beam(
+        &mut self,
+        id_world: &mut impl IdSource,
+        id: BeamId,
+        at: Position,
+        tolerance: Scalar,
+        data_low: B,
+        data_high: B,
+    ) -> Result<Vec<FrameUpdate<B, V>>, SplitBeamError>
+    where
+        B: Clone,
+        V: Clone + Default,
+    {
+        let Some((down, up)) = self.graph.beam_endpoints(id) else {
+            return Err(SplitBeamError::MissingBeam(id));
+        };
+
+        if !at.is_finite() || closest_point_on_segment(at, down, up).distance(at) > tolerance {
+            return Err(SplitBeamError::OffSegment);
+        }
+
+        if !self.within_limit(1) {
+            return Err(SplitBeamError::LimitExceeded);
+        }
+
+        let new_vertex = id_world.next();
+
+        let mut updates = vec![
+            FrameUpdate::AddBeam {
+                vertex_a: id.down_vertex(),
+                new_a: None,
+                vertex_b: new_vertex,
+                new_b: Some((at, V::default())),
+                beam_data: data_low,
+            },
+            FrameUpdate::AddBeam {
+                vertex_a: new_vertex,
+                new_a: None,
+                vertex_b: id.up_vertex(),
+                new_b: None,
+                beam_data: data_high,
+            },
+        ];
+
+        for update in updates.iter() {
+            self.graph
+                .apply_update(update.clone())
+                .expect("Both beams are between existing vertices and a new one.");
+            self.record(update);
+        }
+
+        // the old beam's vertices are still connected to the new beams, so only it's data is lost
+        let removal = FrameUpdate::RemoveBeam {
+            id,
+            recovered: self.graph.removed_beam_data(id),
+        };
+
+        self.graph.remove_beam(id);
+        self.record(&removal);
+        updates.push(removal);
+
+        Ok(updates)
+    }
+
+    /// Replaces a vertex with exactly two beams and the beams with a single beam between it's neighbors,
+    /// eg. to straighten out a joint after editing. This is the opposite of [ShipFrame::split_beam].
+    ///
+    /// Returns the updates to send to clients, which add the new beam and then remove the old ones,
+    /// or `None` if the vertex doesn't have two beams or it's neighbors are already connected.
+    pub fn dissolve_vertex(
+        &mut self,
+        id: VertexId,
+        merged_data: B,
+    ) -> Option<Vec<FrameUpdate<B, V>>>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let [connection_a, connection_b] = self.graph.get_vertex(id)?.connections() else {
+            return None;
+        };
+
+        let (beam_a, beam_b) = (connection_a.beam_id, connection_b.beam_id);
+        let neighbor_a = connection_a.far_vertex();
+        let neighbor_b = connection_b.far_vertex();
+
+        if self
+            .graph
+            .get_beam(BeamId::from_vertices(neighbor_a, neighbor_b))
+            .is_some()
+        {
+            return None;
+        }
+
+        let addition = FrameUpdate::AddBeam {
+            vertex_a: neighbor_a,
+            new_a: None,
+            vertex_b: neighbor_b,
+            new_b: None,
+            beam_data: merged_data,
+        };
+
+        self.graph
+            .apply_update(addition.clone())
+            .expect("The neighbors exist and aren't connected.");
+        self.record(&addition);
+
+        let mut updates = vec![addition];
+
+        // the neighbors stay connected by the new beam, so only the dissolved vertex is removed
+        for beam_id in [beam_a, beam_b] {
+            let removal = FrameUpdate::RemoveBeam {
+                id: beam_id,
+                recovered: self.graph.removed_beam_data(beam_id),
+            };
+
+            self.graph.remove_beam(beam_id);
+            self.record(&removal);
+            updates.push(removal);
+        }
+
+        Some(updates)
+    }
+
+    /// Moves a set of vertices and their beams into a new frame, keeping their ids.
+    ///
+    /// See [Graph::split_off].
+    pub fn split_off(&mut self, vertices: &[VertexId]) -> Result<ShipFrame<B, V>, SplitError>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let graph = self.graph.split_off(vertices)?;
+
+        // clients remove the beams one at a time, so each vertex goes with it's last beam
+        let mut degrees: HashMap<VertexId, usize> = graph
+            .vertices
+            .iter()
+            .map(|(&id, vertex)| (id, vertex.connections.len()))
+            .collect();
+
+        for (&id, beam_data) in graph.beams.iter() {
+            let mut removed_vertices = Vec::new();
+
+            for vertex_id in [id.down_vertex(), id.up_vertex()] {
+                let degree = degrees
+                    .get_mut(&vertex_id)
+                    .expect("Vertex should exist if beam exists.");
+                *degree -= 1;
+
+                if *degree == 0 {
+                    let vertex = &graph.vertices[&vertex_id];
+                    removed_vertices.push((vertex_id, vertex.position, vertex.data.clone()));
+                }
+            }
+
+            self.push_update(FrameUpdate::RemoveBeam {
+                id,
+                recovered: Some(RemovedBeamData {
+                    beam_data: beam_data.clone(),
+                    removed_vertices,
+                }),
+            });
+        }
+
+        Ok(ShipFrame::from_graph(graph))
+    }
+
+    /// Absorbs all vertices and beams of another frame,
+    /// then joins them with a beam for each of the given vertex pairs and beam data.
+    ///
+    /// Both frames should be from the same [IdSource].
+    /// Returns the updates to send to clients of this frame.
+    ///
+    /// Vertices of the other frame without any beams are dropped, as updates can only add
+    /// vertices along with a beam, so joins can't reference them.
+    ///
+    /// The frame is left unmodified if an error is returned.
+    pub fn merge(
+        &mut self,
+        mut other: ShipFrame<B, V>,
+        joins: Vec<(VertexId, VertexId, B)>,
+    ) -> Result<Vec<FrameUpdate<B, V>>, MergeError>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        other
+            .graph
+            .vertices
+            .retain(|_, vertex| !vertex.connections.is_empty());
+
+        if let Some(&id) = other
+            .graph
+            .vertices
+            .keys()
+            .find(|id| self.graph.vertices.contains_key(*id))
+        {
+            return Err(MergeError::IdCollision(id));
+        }
+
+        if !self.within_limit(other.graph.beam_count() + joins.len()) {
+            return Err(MergeError::LimitExceeded);
+        }
+
+        let mut join_ids = HashSet::new();
+
+        for &(vertex_a, vertex_b, _) in joins.iter() {
+            if vertex_a == vertex_b {
+                return Err(MergeError::InvalidJoin(AddBeamError::SelfLoop));
+            }
+
+            for id in [vertex_a, vertex_b] {
+                if !self.graph.vertices.contains_key(&id) && !other.graph.vertices.contains_key(&id)
+                {
+                    return Err(MergeError::InvalidJoin(AddBeamError::MissingVertex(id)));
+                }
+            }
+
+            let beam_id = BeamId::from_vertices(vertex_a, vertex_b);
+
+            if self.graph.beams.contains_key(&beam_id)
+                || other.graph.beams.contains_key(&beam_id)
+                || !join_ids.insert(beam_id)
+            {
+                return Err(MergeError::InvalidJoin(AddBeamError::DuplicateBeam(
+                    beam_id,
+                )));
+            }
+        }
+
+        let mut updates = Vec::new();
+        let mut sent = HashSet::new();
+
+        for (&id, beam_data) in other.graph.beams.iter() {
+            let (vertex_a, vertex_b) = id.vertices();
+
+            let mut new_vertex = |id: VertexId| {
+                sent.insert(id).then(|| {
+                    let vertex = &other.graph.vertices[&id];
+                    (vertex.position(), vertex.data().clone())
+                })
+            };
+
+            updates.push(FrameUpdate::AddBeam {
+                vertex_a,
+                new_a: new_vertex(vertex_a),
+                vertex_b,
+                new_b: new_vertex(vertex_b),
+                beam_data: beam_data.clone(),
+            });
+        }
+
+        self.graph.vertices.extend(other.graph.vertices);
+        self.graph.beams.extend(other.graph.beams);
+        for update in updates.iter() {
+            self.record(update);
+        }
+
+        for (vertex_a, vertex_b, beam_data) in joins {
+            updates.push(self.add_beam_join(vertex_a, vertex_b, beam_data));
+        }
+
+        Ok(updates)
+    }
+
+    /// Starts a transaction that collects the updates of several edits
+    /// so that they can be committed or rolled back together.
+    pub fn begin(&mut self) -> FrameTransaction<'_, B, V>
+    where
+        B: Clone,
+        V: Clone,
+    {
+        FrameTransaction {
+            frame: self,
+            updates: Vec::new(),
+            inverses: Vec::new(),
         }
     }
 
-    pub fn serialize(&self) -> SerializedGraph<B>
+    pub fn serialize(&self) -> SerializedGraph<B, V>
     where
         B: Clone,
+        V: Clone,
     {
         SerializedGraph::from(&self.graph)
     }
 
-    pub fn iter_vertices(&self) -> impl Iterator<Item = (VertexId, &Vertex)> {
+    /// Serializes the frame along with the sequence number of the next recorded edit,
+    /// which a client built from the snapshot should continue from.
+    ///
+    /// See [crate::client::ShipFrame::from_snapshot].
+    pub fn snapshot(&self) -> (SerializedGraph<B, V>, u64)
+    where
+        B: Clone,
+        V: Clone,
+    {
+        (self.serialize(), self.next_seq)
+    }
+
+    pub fn graph(&self) -> &Graph<B, V> {
+        &self.graph
+    }
+
+    /// Consumes the frame, returning it's graph. Any unsent updates are discarded.
+    pub fn into_graph(self) -> Graph<B, V> {
+        self.graph
+    }
+
+    /// Returns all edits recorded since the last call and clears the record,
+    /// for sending them to clients without [ServerFramePlugin].
+    ///
+    /// [ServerFramePlugin] drains the same record, so only one of them should be used per frame.
+    /// Taken updates still count towards the sequence numbers of later edits.
+    pub fn take_updates(&mut self) -> Vec<FrameUpdate<B, V>> {
+        self.updates
+            .drain(..)
+            .map(|Sequenced { inner, .. }| inner)
+            .collect()
+    }
+
+    /// Removes all vertices and beams, keeping the allocated capacity so the frame can be reused.
+    ///
+    /// Unlike other edits this isn't recorded as an update and any unsent updates are discarded,
+    /// instead clients have to be reset from the returned snapshot.
+    pub fn clear(&mut self) -> FrameReset<B, V> {
+        self.graph.clear();
+        self.updates.clear();
+
+        FrameReset {
+            snapshot: SerializedGraph::default(),
+            next_seq: self.next_seq,
+        }
+    }
+
+    /// Gives every vertex a new id from `id_world`, allocated in ascending order of the old ids,
+    /// returning the map from old ids to new ids so that data kept elsewhere can be moved over.
+    ///
+    /// The new ids are consecutive as long as `id_world` has no freed ids waiting to be reused.
+    /// The old ids aren't freed, see [FrameIdWorld::free].
+    ///
+    /// Like [ShipFrame::clear] this isn't recorded and unsent updates are discarded,
+    /// clients have to be reset from the returned snapshot.
+    #[must_use = "clients are out of sync until they are reset from the snapshot"]
+    pub fn compact_ids(
+        &mut self,
+        id_world: &mut impl IdSource,
+    ) -> (BTreeMap<VertexId, VertexId>, FrameReset<B, V>)
+    where
+        B: Clone,
+        V: Clone,
+    {
+        let serialized = std::mem::take(&mut self.graph).into_serialized();
+        let (serialized, map) = id_world.map_graph_with_remap(serialized);
+
+        self.graph = serialized.clone().into();
+        self.updates.clear();
+
+        let reset = FrameReset {
+            snapshot: serialized,
+            next_seq: self.next_seq,
+        };
+
+        (map, reset)
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.graph.vertex_count()
+    }
+
+    pub fn beam_count(&self) -> usize {
+        self.graph.beam_count()
+    }
+
+    pub fn iter_vertices(&self) -> impl Iterator<Item = (VertexId, &Vertex<V>)> {
         self.graph.iter_vertices()
     }
+
+    pub fn iter_beams(&self) -> impl Iterator<Item = (BeamId, &B)> {
+        self.graph.iter_beams()
+    }
+}
+
+impl<B, V> FrameView<B, V> for ShipFrame<B, V> {
+    fn graph(&self) -> &Graph<B, V> {
+        &self.graph
+    }
+}
+
+/// A snapshot that clients have to be reset from after an edit that isn't recorded as an update,
+/// eg. [ShipFrame::clear], see [crate::client::ShipFrame::reset_from].
+#[derive(Serialize, Deserialize, Clone)]
+#[must_use = "clients are out of sync until they are reset from the snapshot"]
+pub struct FrameReset<B, V = ()> {
+    pub snapshot: SerializedGraph<B, V>,
+    /// The sequence number of the next recorded edit, which clients continue from.
+    pub next_seq: u64,
+}
+
+/// A set of edits to a [ShipFrame] that are kept or reverted together.
+///
+/// Edits are applied to the frame immediately, but they are only recorded for clients
+/// once the transaction is committed. Dropping a transaction without committing it rolls it back.
+///
+/// An edit that can't be applied returns an error and leaves the frame and transaction as they were,
+/// so the transaction can carry on with other edits.
+pub struct FrameTransaction<'a, B: Clone, V: Clone = ()> {
+    frame: &'a mut ShipFrame<B, V>,
+    updates: Vec<FrameUpdate<B, V>>,
+    inverses: Vec<FrameUpdate<B, V>>,
+}
+
+impl<B: Clone, V: Clone> FrameTransaction<'_, B, V> {
+    /// Same as [ShipFrame::try_add_beam_extend], returning the new vertex.
+    pub fn add_beam_extend(
+        &mut self,
+        id_world: &mut impl IdSource,
+        existing_vertex: VertexId,
+        position: Position,
+        beam_data: B,
+    ) -> Result<VertexId, ApplyError>
+    where
+        V: Default,
+    {
+        self.add_beam_extend_with_data(id_world, existing_vertex, position, V::default(), beam_data)
+    }
+
+    /// Same as [ShipFrame::try_add_beam_extend_with_data], returning the new vertex.
+    pub fn add_beam_extend_with_data(
+        &mut self,
+        id_world: &mut impl IdSource,
+        existing_vertex: VertexId,
+        position: Position,
+        vertex_data: V,
+        beam_data: B,
+    ) -> Result<VertexId, ApplyError> {
+        if !self.frame.within_limit(1) {
+            return Err(ApplyError::AddBeam(AddBeamError::LimitExceeded));
+        }
+
+        if self.frame.graph.get_vertex(existing_vertex).is_none() {
+            return Err(ApplyError::MissingVertex(existing_vertex));
+        }
+
+        if !position.is_finite() {
+            return Err(ApplyError::AddBeam(AddBeamError::NonFinitePosition(
+                existing_vertex,
+            )));
+        }
+
+        let new_vertex = id_world.next();
+
+        self.apply(FrameUpdate::AddBeam {
+            vertex_a: existing_vertex,
+            new_a: None,
+            vertex_b: new_vertex,
+            new_b: Some((position, vertex_data)),
+            beam_data,
+        })?;
+
+        Ok(new_vertex)
+    }
+
+    /// Same as [ShipFrame::try_add_beam_join].
+    pub fn add_beam_join(
+        &mut self,
+        vertex_a: VertexId,
+        vertex_b: VertexId,
+        beam_data: B,
+    ) -> Result<(), ApplyError> {
+        self.apply(FrameUpdate::AddBeam {
+            vertex_a,
+            new_a: None,
+            vertex_b,
+            new_b: None,
+            beam_data,
+        })
+    }
+
+    /// Same as [ShipFrame::move_vertex].
+    pub fn move_vertex(&mut self, id: VertexId, position: Position) -> Result<(), ApplyError> {
+        self.apply(FrameUpdate::MoveVertex { id, position })
+    }
+
+    /// Same as [ShipFrame::update_beam_data].
+    pub fn update_beam_data(&mut self, id: BeamId, beam_data: B) -> Result<(), ApplyError> {
+        self.apply(FrameUpdate::UpdateBeamData { id, beam_data })
+    }
+
+    /// Same as [ShipFrame::update_vertex_data].
+    pub fn update_vertex_data(&mut self, id: VertexId, vertex_data: V) -> Result<(), ApplyError> {
+        self.apply(FrameUpdate::UpdateVertexData { id, vertex_data })
+    }
+
+    /// Same as [ShipFrame::remove_beams_in_sphere].
+    pub fn remove_beams_in_sphere(&mut self, center: Position, radius: Scalar) {
+        for id in self.frame.graph.beams_in_radius(center, radius) {
+            self.apply(FrameUpdate::RemoveBeam {
+                id,
+                recovered: self.frame.graph.removed_beam_data(id),
+            })
+            .expect("The beam was just found in the graph.");
+        }
+    }
+
+    fn apply(&mut self, update: FrameUpdate<B, V>) -> Result<(), ApplyError> {
+        if matches!(update, FrameUpdate::AddBeam { .. }) && !self.frame.within_limit(1) {
+            return Err(ApplyError::AddBeam(AddBeamError::LimitExceeded));
+        }
+
+        let context = update.unapply_context(&self.frame.graph);
+        self.frame.graph.apply_update(update.clone())?;
+
+        let inverse = context
+            .and_then(|context| update.inverse_from_context(&context).ok())
+            .expect("An update that applied can be inverted.");
+
+        self.inverses.push(inverse);
+        self.updates.push(update);
+        Ok(())
+    }
+
+    /// Keeps the edits and records them, to be sent to clients like any other edit.
+    ///
+    /// Also returns the recorded updates, eg. to send them as one [FrameUpdateBatch](crate::messages::FrameUpdateBatch).
+    pub fn commit(mut self) -> Vec<FrameUpdate<B, V>> {
+        self.inverses.clear();
+
+        let updates = std::mem::take(&mut self.updates);
+
+        for update in updates.iter() {
+            self.frame.push_update(update.clone());
+        }
+
+        updates
+    }
+
+    /// Reverts the frame to its state before the transaction began, nothing is recorded.
+    pub fn rollback(self) {}
+}
+
+impl<B: Clone, V: Clone> Drop for FrameTransaction<'_, B, V> {
+    fn drop(&mut self) {
+        while let Some(inverse) = self.inverses.pop() {
+            // rolling back restores an earlier state, so it isn't limited
+            self.frame
+                .graph
+                .apply_update(inverse)
+                .expect("Inverse updates should apply in reverse order.");
+        }
+    }
+}
+
+/// An edit made to the [ShipFrame] on an entity.
+#[derive(Event)]
+pub struct ServerFrameEvent<B, V = ()> {
+    pub frame: Entity,
+    pub update: Sequenced<FrameUpdate<B, V>>,
+}
+
+/// Sends a [ServerFrameEvent] for every edit made to a [ShipFrame] in [PostUpdate].
+pub struct ServerFramePlugin<B, V = ()>(PhantomData<fn() -> (B, V)>);
+
+impl<B, V> Default for ServerFramePlugin<B, V> {
+    fn default() -> Self {
+        ServerFramePlugin(PhantomData)
+    }
+}
+
+impl<B: Send + Sync + 'static, V: Send + Sync + 'static> Plugin for ServerFramePlugin<B, V> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ServerFrameEvent<B, V>>();
+        app.add_systems(PostUpdate, send_server_frame_events::<B, V>);
+    }
+}
+
+fn send_server_frame_events<B: Send + Sync + 'static, V: Send + Sync + 'static>(
+    mut frames: Query<(Entity, &mut ShipFrame<B, V>)>,
+    mut events: EventWriter<ServerFrameEvent<B, V>>,
+) {
+    for (entity, mut frame) in frames.iter_mut() {
+        if frame.updates.is_empty() {
+            continue;
+        }
+
+        // sending the recorded edits doesn't change the frame
+        let updates = std::mem::take(&mut frame.bypass_change_detection().updates);
+
+        events.send_batch(updates.into_iter().map(|update| ServerFrameEvent {
+            frame: entity,
+            update,
+        }));
+    }
+}
+
+/// Maps the vertices of every [ShipFrame] to the entity of their frame,
+/// eg. to find which frame a raycast hit.
+///
+/// Kept up to date by [FrameRegistryPlugin] as frames are edited, split, merged and removed.
+/// Entries are updated in [PostUpdate], so edits made since then aren't reflected yet.
+#[derive(Resource, Default)]
+pub struct FrameRegistry {
+    frames: HashMap<VertexId, Entity>,
+    vertices: HashMap<Entity, Vec<VertexId>>,
+}
+
+impl FrameRegistry {
+    /// The entity of the frame a vertex is in.
+    pub fn frame_of(&self, id: VertexId) -> Option<Entity> {
+        self.frames.get(&id).copied()
+    }
+
+    /// Every vertex of the frame on an entity, in no particular order.
+    pub fn vertices_of(&self, frame: Entity) -> &[VertexId] {
+        self.vertices.get(&frame).map_or(&[], Vec::as_slice)
+    }
+
+    fn remove_frame(&mut self, frame: Entity) {
+        for id in self.vertices.remove(&frame).unwrap_or_default() {
+            // a vertex that moved to another frame may already belong to it
+            if self.frames.get(&id) == Some(&frame) {
+                self.frames.remove(&id);
+            }
+        }
+    }
+
+    fn insert_frame(&mut self, frame: Entity, vertices: Vec<VertexId>) {
+        for &id in vertices.iter() {
+            self.frames.insert(id, frame);
+        }
+
+        self.vertices.insert(frame, vertices);
+    }
+}
+
+/// Keeps the [FrameRegistry] up to date with the [ShipFrame]s in [PostUpdate].
+///
+/// The entries of a frame are rebuilt whenever it changes, which is linear in it's size.
+/// Frames with different beam or vertex data share the same registry.
+pub struct FrameRegistryPlugin<B, V = ()>(PhantomData<fn() -> (B, V)>);
+
+impl<B, V> Default for FrameRegistryPlugin<B, V> {
+    fn default() -> Self {
+        FrameRegistryPlugin(PhantomData)
+    }
+}
+
+impl<B: Send + Sync + 'static, V: Send + Sync + 'static> Plugin for FrameRegistryPlugin<B, V> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameRegistry>();
+        app.add_systems(PostUpdate, update_frame_registry::<B, V>);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_frame_registry<B: Send + Sync + 'static, V: Send + Sync + 'static>(
+    mut registry: ResMut<FrameRegistry>,
+    frames: Query<(Entity, &ShipFrame<B, V>), Changed<ShipFrame<B, V>>>,
+    mut removed: RemovedComponents<ShipFrame<B, V>>,
+) {
+    for entity in removed.read() {
+        registry.remove_frame(entity);
+    }
+
+    for (entity, frame) in frames.iter() {
+        registry.remove_frame(entity);
+        registry.insert_frame(entity, frame.graph.vertices.keys().copied().collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_resync_from_unrecorded_edits() {
+        let mut id_world = FrameIdWorld::default();
+        let mut frame = ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32);
+        let mut client = crate::client::ShipFrame::new(frame.serialize());
+
+        let vertex = frame.iter_vertices().next().unwrap().0;
+        frame.add_beam_extend(&mut id_world, vertex, Position::Y, 1);
+
+        let (map, reset) = frame.compact_ids(&mut FrameIdWorld::default());
+        assert_eq!(map.len(), 3);
+        assert!(frame.take_updates().is_empty());
+
+        client.reset_from(reset.snapshot, reset.next_seq).unwrap();
+        assert!(client.graph() == frame.graph());
+
+        let reset = frame.clear();
+        client.reset_from(reset.snapshot, reset.next_seq).unwrap();
+        assert_eq!(client.vertex_count(), 0);
+    }
+
+    #[test]
+    fn merge_drops_beamless_vertices() {
+        let mut id_world = FrameIdWorld::default();
+        let mut frame = ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32);
+        let mut client = crate::client::ShipFrame::new(frame.serialize());
+        let vertex = frame.iter_vertices().next().unwrap().0;
+
+        let isolated = id_world.next();
+        let other_frame = |id_world: &mut FrameIdWorld| {
+            let other = ShipFrame::new_from_beam(id_world, Position::Y, Position::Z, 1u32);
+            let mut serialized = other.serialize();
+            serialized.vertices.push((isolated, Position::ONE, ()));
+            ShipFrame::from_serialized(serialized)
+        };
+
+        let other = other_frame(&mut id_world);
+        assert!(matches!(
+            frame.merge(other, vec![(vertex, isolated, 2)]),
+            Err(MergeError::InvalidJoin(AddBeamError::MissingVertex(id))) if id == isolated
+        ));
+
+        let other = other_frame(&mut id_world);
+        let other_vertex = other.iter_vertices().next().unwrap().0;
+
+        for update in frame.merge(other, vec![(vertex, other_vertex, 2)]).unwrap() {
+            client.apply_update(update).unwrap();
+        }
+
+        assert!(!frame.graph().contains_vertex(isolated));
+        assert!(client.graph() == frame.graph());
+    }
+
+    #[test]
+    fn transaction_rejects_edits_and_records_once() {
+        let mut id_world = FrameIdWorld::default();
+        let mut frame = ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32)
+            .with_limit(1);
+        let (down, up) = frame.iter_beams().next().unwrap().0.vertices();
+
+        let mut transaction = frame.begin();
+        assert_eq!(
+            transaction.add_beam_extend(&mut id_world, down, Position::Y, 1),
+            Err(ApplyError::AddBeam(AddBeamError::LimitExceeded))
+        );
+        assert!(transaction.add_beam_join(down, up, 1).is_err());
+        transaction.move_vertex(up, Position::Z).unwrap();
+        transaction
+            .update_beam_data(BeamId::from_vertices(down, up), 2)
+            .unwrap();
+        transaction.rollback();
+
+        assert!(frame.take_updates().is_empty());
+        assert_eq!(
+            frame.graph().get_vertex(up).unwrap().position(),
+            Position::X
+        );
+
+        let mut transaction = frame.begin();
+        transaction.move_vertex(up, Position::Z).unwrap();
+        transaction
+            .update_beam_data(BeamId::from_vertices(down, up), 2)
+            .unwrap();
+        let committed = transaction.commit();
+
+        assert_eq!(committed.len(), 2);
+        assert!(matches!(
+            committed[0],
+            FrameUpdate::MoveVertex { id, .. } if id == up
+        ));
+        assert!(matches!(
+            committed[1],
+            FrameUpdate::UpdateBeamData { beam_data: 2, .. }
+        ));
+
+        assert!(frame.take_updates() == committed);
+        assert_eq!(
+            frame.graph().get_vertex(up).unwrap().position(),
+            Position::Z
+        );
+    }
+
+    #[test]
+    fn rejected_extends_dont_take_ids() {
+        let mut id_world = FrameIdWorld::default();
+        let mut frame = ShipFrame::new_from_beam(&mut id_world, Position::ZERO, Position::X, 0u32);
+        let vertex = frame.iter_vertices().next().unwrap().0;
+
+        assert_eq!(
+            frame
+                .try_add_beam_extend(&mut id_world, vertex, Position::NAN, 1)
+                .err(),
+            Some(AddBeamError::NonFinitePosition(vertex))
+        );
+
+        let mut transaction = frame.begin();
+        assert_eq!(
+            transaction.add_beam_extend(&mut id_world, vertex, Position::INFINITY, 1),
+            Err(ApplyError::AddBeam(AddBeamError::NonFinitePosition(vertex)))
+        );
+        transaction.rollback();
+
+        // only the two vertices of the first beam were allocated
+        let mut fresh = FrameIdWorld::default();
+        fresh.next();
+        fresh.next();
+        assert_eq!(id_world.next(), fresh.next());
+    }
 }